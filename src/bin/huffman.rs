@@ -1,6 +1,15 @@
 use utils::deflate::*;
 use std::env;
+use std::process;
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    deflate_with_77(args);
-}
\ No newline at end of file
+    if args.len() < 2 {
+        eprintln!("usage: huffman <file>");
+        process::exit(1);
+    }
+    if let Err(err) = deflate_file(&args[1], &CompressOptions::default()) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
@@ -1,7 +1,15 @@
-use std::env;
 use utils::inflate::*;
+use std::env;
+use std::process;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    inflate(args);
-}
\ No newline at end of file
+    if args.len() < 2 {
+        eprintln!("usage: inflate <file.deflate>");
+        process::exit(1);
+    }
+    if let Err(err) = inflate_file(&args[1]) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
@@ -1,7 +1,15 @@
-use std::env;
 use utils::deflate::*;
+use std::env;
+use std::process;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    lz(args);
+    if args.len() < 2 {
+        eprintln!("usage: lz77 <file>");
+        process::exit(1);
+    }
+    if let Err(err) = lz77_file(&args[1]) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
 }
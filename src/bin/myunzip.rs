@@ -1,7 +1,45 @@
 use utils::myunzip::*;
 use std::env;
+use std::path::PathBuf;
+use std::process;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    gen_unzip(args);
-}
\ No newline at end of file
+    let mut args: Vec<String> = env::args().collect();
+    let mut options = ExtractOptions::default();
+    // -u <mask> clears those octal permission bits from the restored modes instead of the process umask
+    // -d <dir> extracts below dir instead of the current directory
+    while args.len() > 2 && (args[1] == "-u" || args[1] == "-d") {
+        if args[1] == "-d" {
+            options.dest = PathBuf::from(&args[2]);
+        }
+        else {
+            match u32::from_str_radix(&args[2], 8) {
+                Ok(umask) if umask <= 0o777 => options.umask = umask,
+                _ => {
+                    eprintln!("myunzip: bad umask {}", args[2]);
+                    process::exit(1);
+                }
+            }
+        }
+        args.drain(1..3);
+    }
+    if args.len() < 2 {
+        eprintln!("usage: myunzip [-u umask] [-d dir] <archive.zip>");
+        process::exit(1);
+    }
+    match unzip_archive(&args[1], &options) {
+        Ok(rejected) => {
+            for entry in &rejected {
+                eprintln!("{}", entry);
+            }
+            // Like unzip, skipped entries still count against the exit status
+            if !rejected.is_empty() {
+                process::exit(1);
+            }
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
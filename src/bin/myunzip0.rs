@@ -1,7 +1,25 @@
 use utils::myunzip::*;
 use std::env;
+use std::process;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    gen_unzip_0(args);
-}
\ No newline at end of file
+    if args.len() < 2 {
+        eprintln!("usage: myunzip0 <archive.zip>");
+        process::exit(1);
+    }
+    match unzip_archive(&args[1], &ExtractOptions { raw: true, ..ExtractOptions::default() }) {
+        Ok(rejected) => {
+            for entry in &rejected {
+                eprintln!("{}", entry);
+            }
+            if !rejected.is_empty() {
+                process::exit(1);
+            }
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
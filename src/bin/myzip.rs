@@ -1,7 +1,28 @@
 use utils::myzip::*;
+use utils::zipfile::*;
 use std::env;
+use std::process;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    gen_zip(args);
-}
\ No newline at end of file
+    let mut args: Vec<String> = env::args().collect();
+    let mut level: u8 = 6;
+    let mut symlinks = false;
+    // -0 through -9 pick the compression level and -y stores symlinks as links, as with system zip
+    while args.len() > 1 {
+        match args[1].strip_prefix('-').and_then(|digit| digit.parse::<u8>().ok()) {
+            Some(value) if value <= 9 && args[1].len() == 2 => level = value,
+            _ if args[1] == "-y" => symlinks = true,
+            _ => break,
+        }
+        args.remove(1);
+    }
+    if args.len() < 3 {
+        eprintln!("usage: myzip [-0..-9] [-y] <archive.zip> <path>...");
+        process::exit(1);
+    }
+    let options = ArchiveOptions { file: FileOptions::with_level(level), symlinks };
+    if let Err(err) = zip_paths(&args[1], &args[2..], &options) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
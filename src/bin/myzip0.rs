@@ -1,7 +1,17 @@
 use utils::myzip::*;
+use utils::zipfile::*;
 use std::env;
+use std::process;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    gen_zip_0(args);
-}
\ No newline at end of file
+    if args.len() < 3 {
+        eprintln!("usage: myzip0 <archive.zip> <path>...");
+        process::exit(1);
+    }
+    // Same as myzip -0
+    if let Err(err) = zip_paths(&args[1], &args[2..], &ArchiveOptions { file: FileOptions::with_level(0), ..ArchiveOptions::default() }) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,232 @@
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use super::helpers::*;
+
+const BUFFER_SIZE: usize = 4096;
+
+// Reads a DEFLATE bit stream, least significant bit of each byte first, through a 64-bit accumulator
+pub struct BitReader<R: Read> {
+    inner: R,
+    buffer: Vec<u8>,
+    buf_pos: usize,
+    buf_len: usize,
+    acc: u64,
+    nbits: usize,
+    consumed: u64,
+    eof: bool,
+}
+
+impl<R: Read> BitReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buffer: vec![0; BUFFER_SIZE],
+            buf_pos: 0,
+            buf_len: 0,
+            acc: 0,
+            nbits: 0,
+            consumed: 0,
+            eof: false,
+        }
+    }
+
+    fn fill_buffer(&mut self) -> io::Result<()> {
+        loop {
+            match self.inner.read(&mut self.buffer) {
+                Ok(0) => {
+                    self.eof = true;
+                    break;
+                }
+                Ok(len) => {
+                    self.buf_pos = 0;
+                    self.buf_len = len;
+                    break;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    // Tops the accumulator up to at least n bits, or as many as are left in the input
+    fn refill(&mut self, n: usize) -> io::Result<()> {
+        while self.nbits < n {
+            if self.buf_pos == self.buf_len {
+                if self.eof {
+                    break;
+                }
+                self.fill_buffer()?;
+                continue;
+            }
+            while self.nbits <= 56 && self.buf_pos < self.buf_len {
+                self.acc |= (self.buffer[self.buf_pos] as u64) << self.nbits;
+                self.buf_pos += 1;
+                self.nbits += 8;
+            }
+        }
+        Ok(())
+    }
+
+    fn eof_error(&self) -> io::Error {
+        io::Error::new(io::ErrorKind::UnexpectedEof, format!("Unexpected end of input at bit {}", self.consumed))
+    }
+
+    // Returns the next n (at most 32) bits without consuming them, zero-padded if the input ends first
+    pub fn peek(&mut self, n: usize) -> io::Result<usize> {
+        self.refill(n)?;
+        Ok((self.acc & ((1u64 << n) - 1)) as usize)
+    }
+
+    pub fn consume(&mut self, n: usize) -> io::Result<()> {
+        self.refill(n)?;
+        if n > self.nbits {
+            return Err(self.eof_error());
+        }
+        self.acc >>= n;
+        self.nbits -= n;
+        self.consumed += n as u64;
+        Ok(())
+    }
+
+    pub fn read_bits(&mut self, n: usize) -> io::Result<usize> {
+        let ret = self.peek(n)?;
+        self.consume(n)?;
+        Ok(ret)
+    }
+
+    // Drops the rest of a partially read byte
+    pub fn align_to_byte(&mut self) {
+        let partial = self.nbits % 8;
+        self.acc >>= partial;
+        self.nbits -= partial;
+        self.consumed += partial as u64;
+    }
+
+    // Fills buf with whole bytes, which must start on a byte boundary
+    pub fn read_bytes(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        let mut filled = 0;
+        while filled < buf.len() && self.nbits >= 8 {
+            buf[filled] = self.acc as u8;
+            self.acc >>= 8;
+            self.nbits -= 8;
+            filled += 1;
+        }
+        while filled < buf.len() {
+            if self.buf_pos == self.buf_len {
+                if self.eof {
+                    self.consumed += 8 * filled as u64;
+                    return Err(self.eof_error());
+                }
+                self.fill_buffer()?;
+                continue;
+            }
+            let len = (buf.len() - filled).min(self.buf_len - self.buf_pos);
+            buf[filled..filled + len].copy_from_slice(&self.buffer[self.buf_pos..self.buf_pos + len]);
+            self.buf_pos += len;
+            filled += len;
+        }
+        self.consumed += 8 * buf.len() as u64;
+        Ok(())
+    }
+
+    // Number of bits consumed so far
+    pub fn position(&self) -> u64 {
+        self.consumed
+    }
+}
+
+// Writes a DEFLATE bit stream, least significant bit of each byte first, through a 64-bit accumulator
+pub struct BitWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+    acc: u64,
+    nbits: usize,
+    written: u64,
+}
+
+impl<W: Write> BitWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+            acc: 0,
+            nbits: 0,
+            written: 0,
+        }
+    }
+
+    // Writes the low nbits (at most 32) of value, least significant bit first
+    pub fn write(&mut self, value: usize, nbits: usize) -> io::Result<()> {
+        self.acc |= ((value as u64) & ((1u64 << nbits) - 1)) << self.nbits;
+        self.nbits += nbits;
+        self.written += nbits as u64;
+        if self.nbits >= 32 {
+            self.buffer.extend_from_slice(&(self.acc as u32).to_le_bytes());
+            self.acc >>= 32;
+            self.nbits -= 32;
+            if self.buffer.len() >= BUFFER_SIZE {
+                self.flush_buffer()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Huffman codes go out most significant bit first
+    pub fn write_code(&mut self, code: usize, len: usize) -> io::Result<()> {
+        self.write(reverse_bits(code, len), len)
+    }
+
+    // Pads the current byte with zero bits
+    pub fn align_to_byte(&mut self) -> io::Result<()> {
+        let partial = (8 - self.nbits % 8) % 8;
+        self.write(0, partial)
+    }
+
+    // Writes whole bytes, which must start on a byte boundary
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        while self.nbits > 0 {
+            self.buffer.push(self.acc as u8);
+            self.acc >>= 8;
+            self.nbits -= 8;
+        }
+        self.buffer.extend_from_slice(bytes);
+        self.written += 8 * bytes.len() as u64;
+        if self.buffer.len() >= BUFFER_SIZE {
+            self.flush_buffer()?;
+        }
+        Ok(())
+    }
+
+    fn flush_buffer(&mut self) -> io::Result<()> {
+        self.inner.write_all(&self.buffer)?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    // Number of bits written so far
+    pub fn position(&self) -> u64 {
+        self.written
+    }
+
+    // Pushes every whole byte written so far through to the sink; a partial byte stays behind
+    pub fn flush(&mut self) -> io::Result<()> {
+        while self.nbits >= 8 {
+            self.buffer.push(self.acc as u8);
+            self.acc >>= 8;
+            self.nbits -= 8;
+        }
+        self.flush_buffer()?;
+        self.inner.flush()
+    }
+
+    // Pads out the last byte, flushes everything to the sink and hands it back
+    pub fn finish(mut self) -> io::Result<W> {
+        self.align_to_byte()?;
+        self.write_bytes(&[])?;
+        self.flush_buffer()?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
@@ -1,34 +1,44 @@
 use std::fs::File;
-use std::io::Read;
-use std::io::Write;
-use super::helpers::*;
-use std::collections::HashMap;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+use super::huffman::*;
+use super::bitstream::*;
+use super::error::*;
 
-struct BitStreamDeflator{
+struct BitStreamDeflator<W: Write> {
     data: Vec<u8>,
-    lz77map: HashMap<Vec<u8>, Vec<usize>>,
+    // Most recent position for each hash of three bytes, and for each position the one before it with the same hash
+    head: Vec<usize>,
+    prev: Vec<usize>,
+    max_chain: usize,
+    nice_length: usize,
+    good_length: usize,
+    max_lazy: usize,
     data_pos: usize,
     lz_pos: usize,
     cur_block_type: usize,
-    look_back_buffer: Vec<u8>,
-    bit_buffer: Vec<bool>,
+    writer: BitWriter<W>,
     finished: bool,
     lz77_output: Vec<usize>,
     lz77_internal: Vec<(usize, usize, usize)>,
 }
 
 
-impl BitStreamDeflator {
+impl<W: Write> BitStreamDeflator<W> {
 
-    pub fn new(data: Vec<u8>) -> Self {
+    pub fn new(data: Vec<u8>, writer: W, config: &LevelConfig) -> Self {
         Self {
             data,
-            lz77map: HashMap::new(),
+            head: vec![NO_POS; HASH_SIZE],
+            prev: vec![NO_POS; WINDOW_SIZE],
+            max_chain: config.max_chain,
+            nice_length: config.nice_length,
+            good_length: config.good_length,
+            max_lazy: config.max_lazy,
             data_pos: 0,
             lz_pos: 0,
             cur_block_type: 0,
-            look_back_buffer: Vec::new(),
-            bit_buffer: Vec::new(),
+            writer: BitWriter::new(writer),
             finished: false,
             lz77_output: Vec::new(),
             lz77_internal: Vec::new(),
@@ -42,22 +52,6 @@ impl BitStreamDeflator {
         return ret
     }
 
-    fn read_triplet(&mut self) -> Vec<u8> {
-        let mut buffer: Vec<u8> = Vec::new();
-        while(buffer.len() != 3){
-            buffer.push(self.data[self.data_pos + buffer.len()]);
-        }
-        return buffer;
-    }
-
-    fn read_triplet_at(&self, at: usize) -> Vec<u8> {
-        let mut buffer: Vec<u8> = Vec::new();
-        while(buffer.len() != 3){
-            buffer.push(self.data[at + buffer.len()]);
-        }
-        return buffer;
-    }
-
     fn read_next_from(&self, loc: usize) -> u8 {
         return self.data[loc];
     }
@@ -76,315 +70,813 @@ impl BitStreamDeflator {
     const EOB_CODE: usize = 256;
 
     const MATCH_7_LOW: usize = 0;
-    const MATCH_7_HIGH: usize = 23;
     const MATCH_8_1_LOW: usize = 48;
-    const MATCH_8_1_HIGH: usize = 191;
     const MATCH_8_2_LOW: usize = 192;
-    const MATCH_8_2_HIGH: usize = 199;
     const MATCH_9_LOW: usize = 400;
-    const MATCH_9_HIGH: usize = 511;
 
     const BASE_7_CODE: usize = 256;
     const BASE_8_1_CODE: usize = 0;
     const BASE_8_2_CODE: usize = 280;
     const BASE_9_CODE: usize = 144;
 
-    const BFINAL: bool = true;
-    const BTYPE: [bool; 2]= [true, false];
+    const BTYPE: usize = 1;
+
+    const STORED_BTYPE: usize = 0;
+    const MAX_STORED_LEN: usize = 65535;
+
+    // Writes data_pos..end as stored blocks, splitting where one would overflow its 16-bit length
+    fn write_stored(&mut self, end: usize, last: bool) -> io::Result<()> {
+        loop {
+            let block_end = end.min(self.data_pos + Self::MAX_STORED_LEN);
+            let len = block_end - self.data_pos;
+            self.writer.write((last && block_end == end) as usize, 1)?;
+            self.writer.write(Self::STORED_BTYPE, 2)?;
+            self.writer.align_to_byte()?;
+            self.writer.write(len, 16)?;
+            self.writer.write(!len & 0xffff, 16)?;
+            self.writer.write_bytes(&self.data[self.data_pos..block_end])?;
+            self.data_pos = block_end;
+            if block_end == end {
+                return Ok(());
+            }
+        }
+    }
 
-    fn write_huffman(&mut self){
-        self.bit_buffer.push(BitStreamDeflator::BFINAL);
-        self.bit_buffer.extend(Vec::from(BitStreamDeflator::BTYPE));
+    // Flattens the data and its lz77 matches into (literal, 0) and (length, distance) pairs
+    fn lz77_symbols(&mut self) -> Vec<(usize, usize)> {
+        let mut symbols: Vec<(usize, usize)> = Vec::new();
+        let mut next_skip = self.lz77_read_next();
         while self.data_pos < self.data.len() {
-            let cur_val = self.read_next();
-            
-            let mut cur_code = cur_val + BitStreamDeflator::MATCH_8_1_LOW - BitStreamDeflator::BASE_8_1_CODE;
-            let size: u8;
-            if BitStreamDeflator::MATCH_8_1_LOW <= cur_code && cur_code <= BitStreamDeflator::MATCH_8_1_HIGH {
-                size = 8;
-            }
-            else{
-                cur_code = cur_val + BitStreamDeflator::MATCH_9_LOW - BitStreamDeflator::BASE_9_CODE;
-                size = 9;
+            match next_skip {
+                Some((pos, amount_skip, dist_back)) if pos == self.data_pos => {
+                    symbols.push((amount_skip, dist_back));
+                    self.data_pos += amount_skip;
+                    next_skip = self.lz77_read_next();
+                }
+                _ => {
+                    let cur_val = self.read_next();
+                    symbols.push((cur_val, 0));
+                }
             }
-            let bits = usize_to_bits(cur_code, size);
-            self.bit_buffer.extend(bits);
         }
-        self.bit_buffer.extend(vec![false; 7]);  
+        symbols
+    }
 
-        self.prep_look_back_buffer();
+    fn write_fixed_code(&mut self, cur_val: usize) -> io::Result<()> {
+        let (cur_code, size) = match cur_val {
+            0..=143 => (cur_val + Self::MATCH_8_1_LOW - Self::BASE_8_1_CODE, 8),
+            144..=255 => (cur_val + Self::MATCH_9_LOW - Self::BASE_9_CODE, 9),
+            256..=279 => (cur_val + Self::MATCH_7_LOW - Self::BASE_7_CODE, 7),
+            _ => (cur_val + Self::MATCH_8_2_LOW - Self::BASE_8_2_CODE, 8),
+        };
+        self.writer.write_code(cur_code, size)
     }
 
-    fn prep_look_back_buffer(&mut self) {
-        let mut byte_buffer: Vec<bool> = Vec::new();
+    fn write_huffman_with_lz77(&mut self, last: bool, strategy: BlockStrategy) -> io::Result<()> {
+        let start = self.data_pos;
+        let symbols = self.lz77_symbols();
+        self.write_symbols(start, &symbols, last, strategy)
+    }
+
+    // Writes the symbols covering data[start..], cut into blocks wherever their statistics change enough
+    // to pay for another block header
+    fn write_symbols(&mut self, start: usize, symbols: &[(usize, usize)], last: bool, strategy: BlockStrategy) -> io::Result<()> {
+        let mut bounds: Vec<usize> = vec![0];
+        if strategy == BlockStrategy::Cheapest {
+            split_symbols(symbols, 0, symbols.len(), &mut bounds)?;
+        }
+        bounds.push(symbols.len());
+        self.data_pos = start;
+        for i in 1..bounds.len() {
+            let block_last = last && i == bounds.len() - 1;
+            self.write_block_symbols(&symbols[bounds[i - 1]..bounds[i]], block_last, strategy)?;
+        }
+        Ok(())
+    }
 
-        for (i, b) in self.bit_buffer.iter().enumerate() {
-            if (i % 8) == 0 && i > 0 {
-                byte_buffer.reverse();
-                self.look_back_buffer.push(binary_to_dec(&byte_buffer) as u8);
-                byte_buffer = Vec::new()
+    // Writes one block for the symbols starting at data_pos as whichever of stored, fixed or dynamic comes out smallest
+    fn write_block_symbols(&mut self, symbols: &[(usize, usize)], last: bool, strategy: BlockStrategy) -> io::Result<()> {
+        let stats = SymbolStats::new(symbols);
+        let end = self.data_pos + stats.len;
+        let stored = stored_cost(stats.len);
+        let fixed = fixed_cost(&stats);
+        if strategy == BlockStrategy::Cheapest {
+            let tables = DynamicTables::new(&stats)?;
+            let dynamic = tables.cost(&stats);
+            if dynamic < fixed && dynamic < stored {
+                self.data_pos = end;
+                return self.write_dynamic_block(symbols, &tables, last);
             }
-            byte_buffer.push(*b);
         }
-        
-        // byte_buffer.reverse();
-        while (byte_buffer.len() != 8 && byte_buffer.len() > 0) {
-            byte_buffer.push(false);
+        if stored < fixed {
+            return self.write_stored(end, last);
         }
-        self.look_back_buffer.push(binary_to_dec(&byte_buffer) as u8);
+        self.data_pos = end;
+        self.write_fixed_block(symbols, last)
     }
 
-    fn write_huffman_with_lz77(&mut self) {
-        self.bit_buffer.push(BitStreamDeflator::BFINAL);
-        self.bit_buffer.extend(Vec::from(BitStreamDeflator::BTYPE));
-        let mut next_skip = self.lz77_read_next();
+    fn write_fixed_block(&mut self, symbols: &[(usize, usize)], last: bool) -> io::Result<()> {
+        self.writer.write(last as usize, 1)?;
+        self.writer.write(Self::BTYPE, 2)?;
+        for (cur_val, dist_back) in symbols.iter().copied() {
+            if dist_back == 0 {
+                self.write_fixed_code(cur_val)?;
+                continue;
+            }
+            let (len_code, extra_bit_len, extra_bit_val) = length_code(cur_val);
+            self.write_fixed_code(len_code)?;
+            self.writer.write(extra_bit_val, extra_bit_len)?;
+            let (dist_code, extra_bit_len, extra_bit_val) = distance_code(dist_back);
+            self.writer.write_code(dist_code, 5)?;
+            self.writer.write(extra_bit_val, extra_bit_len)?;
+        }
+        self.write_fixed_code(Self::EOB_CODE)
+    }
 
-        while self.data_pos < self.data.len() {
-            let cur_val = self.read_next();
+    const DYNAMIC_BTYPE: usize = 2;
 
-            match next_skip {
-                Some((pos, amount_skip, dist_back)) if pos == self.data_pos - 1 => {
-                    self.data_pos += amount_skip - 1;
-                    next_skip = self.lz77_read_next();
+    fn write_dynamic_block(&mut self, symbols: &[(usize, usize)], tables: &DynamicTables, last: bool) -> io::Result<()> {
+        self.writer.write(last as usize, 1)?;
+        self.writer.write(Self::DYNAMIC_BTYPE, 2)?;
+        self.writer.write(tables.hlit - 257, 5)?;
+        self.writer.write(tables.hdist - 1, 5)?;
+        self.writer.write(tables.hclen - 4, 4)?;
+        for symbol in CODE_LEN_ORDER.iter().take(tables.hclen) {
+            self.writer.write(tables.cl_lens[*symbol], 3)?;
+        }
+        for (symbol, repeat) in tables.code_len_symbols.iter().copied() {
+            self.writer.write_code(tables.cl_codes[symbol], tables.cl_lens[symbol])?;
+            let (extra_bit_len, base) = code_len_extra(symbol);
+            self.writer.write(repeat.saturating_sub(base), extra_bit_len)?;
+        }
 
-                    let mut extra_bit_len: usize = 0;
-                    let mut extra_bit_val: usize = 0;
-                    let mut cur_val: usize = 0;
-                    
-                    
-
-                    match amount_skip {
-                        3..=10 => {cur_val = amount_skip + 254; extra_bit_len = 0; extra_bit_val = 0;}
-                        11..=18 => {cur_val = ((amount_skip - 11) / 2) + 265; extra_bit_len = 1; extra_bit_val = (amount_skip + 1) % 2;}
-                        19..=34 => {cur_val = ((amount_skip - 19) / 4) + 269; extra_bit_len = 2; extra_bit_val = (amount_skip + 1) % 4;}
-                        35..=66 => {cur_val = ((amount_skip - 35) / 8) + 273; extra_bit_len = 3; extra_bit_val = (amount_skip + 5) % 8;}
-                        67..=130 => {cur_val = ((amount_skip - 67) / 16) + 277; extra_bit_len = 4; extra_bit_val = (amount_skip + 13) % 16;}
-                        131..=257 => {cur_val = ((amount_skip - 131) / 32) + 281; extra_bit_len = 5; extra_bit_val = (amount_skip + 29) % 32;}
-                        258 => {cur_val = 285; extra_bit_len = 0; extra_bit_val = 0;}
-                        _ => {}
-                    }
-                    let size: u8;
-                    let mut cur_code = cur_val + BitStreamDeflator::MATCH_7_LOW - BitStreamDeflator::BASE_7_CODE;
-                    if BitStreamDeflator::MATCH_7_LOW <= cur_code && cur_code <= BitStreamDeflator::MATCH_7_HIGH {
-                        size = 7;
-                    }
-                    else{
-                        cur_code = cur_val + BitStreamDeflator::MATCH_8_2_LOW - BitStreamDeflator::BASE_8_2_CODE;
-                        size = 8;
-                    }
-                    let bits = usize_to_bits(cur_code, size);
-                    self.bit_buffer.extend(bits);
-                    if extra_bit_len > 0 {
-                        let mut extra_bits = usize_to_bits(extra_bit_val, extra_bit_len as u8);
-                        extra_bits.reverse();
-                        self.bit_buffer.extend(extra_bits);
-                    }
+        for (cur_val, dist_back) in symbols.iter().copied() {
+            if dist_back == 0 {
+                self.writer.write_code(tables.lit_codes[cur_val], tables.lit_lens[cur_val])?;
+                continue;
+            }
+            let (len_code, extra_bit_len, extra_bit_val) = length_code(cur_val);
+            self.writer.write_code(tables.lit_codes[len_code], tables.lit_lens[len_code])?;
+            self.writer.write(extra_bit_val, extra_bit_len)?;
+            let (dist_code, extra_bit_len, extra_bit_val) = distance_code(dist_back);
+            self.writer.write_code(tables.dist_codes[dist_code], tables.dist_lens[dist_code])?;
+            self.writer.write(extra_bit_val, extra_bit_len)?;
+        }
+        let eob = Self::EOB_CODE;
+        self.writer.write_code(tables.lit_codes[eob], tables.lit_lens[eob])
+    }
 
-                    let mut extra_bit_len: usize = 0;
-                    let mut extra_bit_val: usize = 0;
-                    let mut cur_val: usize = 0;
-
-                    match dist_back{
-                        1..=4 => {cur_val = dist_back - 1; extra_bit_len = 0; extra_bit_val = 0;}
-                        5..=8 => {cur_val = ((dist_back - 5) / 2) + 4; extra_bit_len = 1; extra_bit_val = (dist_back + 1) % 2;}
-                        9..=16 => {cur_val = ((dist_back - 9) / 4) + 6; extra_bit_len = 2; extra_bit_val = (dist_back + 3) % 4;}
-                        17..=32 => {cur_val = ((dist_back - 17) / 8) + 8; extra_bit_len = 3; extra_bit_val = (dist_back + 7) % 8;}
-                        33..=64 => {cur_val = ((dist_back - 33) / 16) + 10; extra_bit_len = 4; extra_bit_val = (dist_back + 15) % 16;}
-                        65..=128 => {cur_val = ((dist_back - 65) / 32) + 12; extra_bit_len = 5; extra_bit_val = (dist_back + 31) % 32;}
-                        129..=256 => {cur_val = ((dist_back - 129) / 64) + 14; extra_bit_len = 6; extra_bit_val = (dist_back + 63) % 64;}
-                        257..=512 => {cur_val = ((dist_back - 257) / 128) + 16; extra_bit_len = 7; extra_bit_val = (dist_back + 127) % 128;}
-                        513..=1024 => {cur_val = ((dist_back - 513) / 256) + 18; extra_bit_len = 8; extra_bit_val = (dist_back + 255) % 256;}
-                        1025..=2048 => {cur_val = ((dist_back - 1025) / 512) + 20; extra_bit_len = 9; extra_bit_val = (dist_back + 511) % 512;}
-                        2049..=4096 => {cur_val = ((dist_back - 2049) / 1024) + 22; extra_bit_len = 10; extra_bit_val = (dist_back + 1023) % 1024;}
-                        4097..=8192 => {cur_val = ((dist_back - 4097) / 2048) + 24; extra_bit_len = 11; extra_bit_val = (dist_back + 2047) % 2048;}
-                        8193..=16384 => {cur_val = ((dist_back - 8193) / 4096) + 26; extra_bit_len = 12; extra_bit_val = (dist_back + 4095) % 4096;}
-                        16385..=32768 => {cur_val = ((dist_back - 16385) / 8192) + 28; extra_bit_len = 13; extra_bit_val = (dist_back + 8191) % 8192;}
-                        _ => {}
-                    }
-                    let bits = usize_to_bits(cur_val, 5);
-                    self.bit_buffer.extend(bits);
-                    if extra_bit_len > 0 {
-                        let mut extra_bits = usize_to_bits(extra_bit_val, extra_bit_len as u8);
-                        extra_bits.reverse();
-                        self.bit_buffer.extend(extra_bits);
-                    }
-                }
-                _ => {
-                    let mut cur_code = cur_val + BitStreamDeflator::MATCH_8_1_LOW - BitStreamDeflator::BASE_8_1_CODE;
-                    let size: u8;
-                    if BitStreamDeflator::MATCH_8_1_LOW <= cur_code && cur_code <= BitStreamDeflator::MATCH_8_1_HIGH {
-                        size = 8;
-                    }
-                    else{
-                        cur_code = cur_val + BitStreamDeflator::MATCH_9_LOW - BitStreamDeflator::BASE_9_CODE; 
-                        size = 9;
-                        
+    const MAX_LEN: usize = 258;
+    const MIN_LEN: usize = 3;
+
+    fn hash_at(&self, pos: usize) -> usize {
+        let key = (self.data[pos] as u32) | (self.data[pos + 1] as u32) << 8 | (self.data[pos + 2] as u32) << 16;
+        (key.wrapping_mul(0x9e3779b1) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert_hash(&mut self, pos: usize) {
+        if pos + Self::MIN_LEN <= self.data.len() {
+            let hash = self.hash_at(pos);
+            self.prev[pos % WINDOW_SIZE] = self.head[hash];
+            self.head[hash] = pos;
+        }
+    }
+
+    // Walks the hash chain for pos, returning the longest (length, distance) found within the window
+    fn longest_match(&self, pos: usize, max_chain: usize) -> (usize, usize) {
+        self.walk_chain(pos, max_chain, |_, _| {})
+    }
+
+    // Same as longest_match, but also hands each match that beats the previous best to longer as it turns up
+    fn walk_chain<F: FnMut(usize, usize)>(&self, pos: usize, max_chain: usize, mut longer: F) -> (usize, usize) {
+        let max_len = Self::MAX_LEN.min(self.data.len() - pos);
+        if max_len < Self::MIN_LEN {
+            return (0, 0);
+        }
+        let mut best = (0, 0);
+        let mut candidate = self.head[self.hash_at(pos)];
+        let mut chain = max_chain;
+        while candidate != NO_POS && pos - candidate <= WINDOW_SIZE && chain > 0 {
+            // Checking the byte just past the current best first rules out most candidates cheaply
+            let probe = best.0.min(max_len - 1);
+            if self.data[candidate + probe] == self.data[pos + probe] {
+                let len = self.data[candidate..candidate + max_len].iter()
+                    .zip(&self.data[pos..pos + max_len])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best.0 {
+                    best = (len, pos - candidate);
+                    longer(len, pos - candidate);
+                    if len == max_len || len >= self.nice_length {
+                        break;
                     }
-                    let bits = usize_to_bits(cur_code, size);
-                    self.bit_buffer.extend(bits);
                 }
             }
+            let next = self.prev[candidate % WINDOW_SIZE];
+            // Chains only ever run backwards; anything else is a slot a newer position has taken over
+            if next == NO_POS || next >= candidate {
+                break;
+            }
+            candidate = next;
+            chain -= 1;
         }
-        self.bit_buffer.extend(vec![false; 7]);  
-
-        let mut bit_buffer_val: Vec<usize> = Vec::new();
-        for i in self.bit_buffer.iter() {
-            bit_buffer_val.push(*i as usize)
+        if best.0 < Self::MIN_LEN {
+            return (0, 0);
         }
-        self.prep_look_back_buffer(); 
+        best
     }
 
-    const MAX_LEN: usize = 258;
+    fn push_lz77_output(&mut self, length: usize, distance: usize) {
+        self.lz77_output.push(60);
+        self.lz77_output.extend(length.to_string().bytes().map(|b| b as usize));
+        self.lz77_output.push(44);
+        self.lz77_output.extend(distance.to_string().bytes().map(|b| b as usize));
+        self.lz77_output.push(62);
+    }
+
+    fn insert_hashes(&mut self, from: usize, to: usize) {
+        for pos in from..to {
+            self.insert_hash(pos);
+        }
+    }
 
-    fn lz77_baseline(&mut self){
-        if self.data.len() > 1 {
-            while self.data_pos < self.data.len()-2{
-                let buffer = self.read_triplet();
-                self.lz77map.entry(buffer.clone()).and_modify(|list| list.insert(0, self.data_pos)).or_insert(vec![self.data_pos]);
-                let lookup = self.lz77map.get(&buffer).unwrap().clone();
-                let mut curr_match = (buffer, 0, 0);
-                if self.data_pos==26{
-                    println!("lookup {:?}", lookup);
+    // Finds matches for everything from start on, letting them reach back into the data before it
+    fn lz77_baseline(&mut self, start: usize){
+        self.head.fill(NO_POS);
+        self.lz77_internal.clear();
+        self.lz77_output.clear();
+        self.lz_pos = 0;
+        self.insert_hashes(start.saturating_sub(WINDOW_SIZE), start);
+        // Everything before hashed is in the chains; a position has to stay out of them until it has been searched
+        let mut hashed = start;
+        self.data_pos = start;
+        while self.data_pos < self.data.len() {
+            let (mut length, mut distance) = self.longest_match(self.data_pos, self.max_chain);
+            // Before settling for a short match, see whether starting one byte later does better
+            while length >= Self::MIN_LEN && length < self.max_lazy && self.data_pos + 1 < self.data.len() {
+                self.insert_hashes(hashed, self.data_pos + 1);
+                hashed = self.data_pos + 1;
+                let chain = if length >= self.good_length { self.max_chain / 4 } else { self.max_chain };
+                let (next_length, next_distance) = self.longest_match(self.data_pos + 1, chain);
+                if next_length <= length {
+                    break;
                 }
-                let curr_match_new = self.is_long_match(&lookup, curr_match.clone());
-                if self.data_pos==26{
-                    println!("curr_new_after_func {:?}", curr_match_new);
+                self.lz77_output.push(self.read_next_from(self.data_pos) as usize);
+                self.data_pos += 1;
+                (length, distance) = (next_length, next_distance);
+            }
+            if length < Self::MIN_LEN {
+                self.lz77_output.push(self.read_next_from(self.data_pos) as usize);
+                length = 1;
+            }
+            else {
+                self.lz77_internal.push((self.data_pos, length, distance));
+                self.push_lz77_output(length, distance);
+            }
+            self.data_pos += length;
+            self.insert_hashes(hashed, self.data_pos);
+            hashed = self.data_pos;
+        }
+        self.data_pos = start;
+    }
+
+    // Every match at each position from start on that is longer than the ones closer to it, as (length, distance)
+    // pairs; the pairs for start + i are matches[match_starts[i]..match_starts[i + 1]]
+    fn all_matches(&mut self, start: usize) -> (Vec<usize>, Vec<(usize, usize)>) {
+        self.head.fill(NO_POS);
+        self.insert_hashes(start.saturating_sub(WINDOW_SIZE), start);
+        let mut match_starts: Vec<usize> = Vec::with_capacity(self.data.len() - start + 1);
+        let mut matches: Vec<(usize, usize)> = Vec::new();
+        for pos in start..self.data.len() {
+            match_starts.push(matches.len());
+            self.walk_chain(pos, self.max_chain, |length, distance| {
+                if length >= Self::MIN_LEN {
+                    matches.push((length, distance));
                 }
-                if (curr_match.1 != curr_match_new.1) && (curr_match.2 != curr_match_new.2){
-                    curr_match = curr_match_new;
-    
-                    self.lz77_internal.push((self.data_pos, curr_match.1, curr_match.2));
-    
-                    self.lz77_output.push(60);
-                    let first_arr = format!("{}", (curr_match.1));
-                    let first_num = first_arr.as_bytes().clone();
-                    for i in first_num.iter() {
-                        self.lz77_output.push(*i as usize);
-                    }
-                    self.lz77_output.push(44);
-                    let second_arr = format!("{}", (curr_match.2));
-                    let second_num = second_arr.as_bytes().clone();
-                    for i in second_num.iter() {
-                        self.lz77_output.push(*i as usize);
-                    }
-                    self.lz77_output.push(62);
-    
-                    for i in self.data_pos + 1..=self.data_pos + curr_match.1 - 1 {
-                        if i < self.data.len() - 2 {
-                            let triplet = self.read_triplet_at(i);
-                            self.lz77map.entry(triplet).and_modify(|list| list.insert(0, i)).or_insert(vec![i]); 
-                        }
+            });
+            self.insert_hash(pos);
+        }
+        match_starts.push(matches.len());
+        (match_starts, matches)
+    }
+
+    // Cheapest way through the data from start on, pricing each literal and match by the given code lengths
+    fn optimal_parse(&self, start: usize, match_starts: &[usize], matches: &[(usize, usize)], lit_lens: &[usize], dist_lens: &[usize]) -> Vec<(usize, usize)> {
+        // Symbols the last tables never used still need a price, and a long one is closer to the truth than none
+        let price = |len: usize| if len == 0 { MAX_CODE_BITS } else { len };
+        let length_bits: Vec<usize> = (0..=Self::MAX_LEN).map(|length| {
+            if length < Self::MIN_LEN {
+                return 0;
+            }
+            let (len_code, len_extra, _) = length_code(length);
+            price(lit_lens[len_code]) + len_extra
+        }).collect();
+        let len = self.data.len() - start;
+        let mut cost: Vec<usize> = vec![usize::MAX; len + 1];
+        // The (length, distance) step that reached each position most cheaply, with distance 0 for a literal
+        let mut step: Vec<(usize, usize)> = vec![(0, 0); len + 1];
+        cost[0] = 0;
+        for i in 0..len {
+            let literal_cost = cost[i] + price(lit_lens[self.data[start + i] as usize]);
+            if literal_cost < cost[i + 1] {
+                cost[i + 1] = literal_cost;
+                step[i + 1] = (1, 0);
+            }
+            // A match also covers every length shorter than itself, down to where the previous pair left off
+            let mut shortest = Self::MIN_LEN;
+            for (length, distance) in matches[match_starts[i]..match_starts[i + 1]].iter().copied() {
+                let (dist_code, dist_extra, _) = distance_code(distance);
+                let dist_bits = price(dist_lens[dist_code]) + dist_extra;
+                for sub_len in shortest..=length {
+                    let match_cost = cost[i] + length_bits[sub_len] + dist_bits;
+                    if match_cost < cost[i + sub_len] {
+                        cost[i + sub_len] = match_cost;
+                        step[i + sub_len] = (sub_len, distance);
                     }
-                    self.data_pos += curr_match.1 - 1;
-                }
-                else{
-                    self.lz77_output.push(self.read_next_from(self.data_pos) as usize);
                 }
-                self.data_pos += 1;
+                shortest = length + 1;
             }
         }
-        while self.data_pos < self.data.len() {
-            self.lz77_output.push(self.read_next_from(self.data_pos) as usize);
-            self.data_pos += 1;
+        let mut symbols: Vec<(usize, usize)> = Vec::new();
+        let mut i = len;
+        while i > 0 {
+            let (length, distance) = step[i];
+            i -= length;
+            if distance == 0 {
+                symbols.push((self.data[start + i] as usize, 0));
+            }
+            else {
+                symbols.push((length, distance));
+            }
         }
-        self.data_pos = 0;
+        symbols.reverse();
+        symbols
     }
 
+    // Starts from the lazy parse and keeps re-parsing against the tables of the previous round,
+    // writing whichever round came out smallest
+    fn write_ultra(&mut self, start: usize, last: bool, strategy: BlockStrategy) -> io::Result<()> {
+        self.lz77_baseline(start);
+        let mut best = self.lz77_symbols();
+        let stats = SymbolStats::new(&best);
+        let mut tables = DynamicTables::new(&stats)?;
+        let mut best_cost = tables.cost(&stats);
+        let (match_starts, matches) = self.all_matches(start);
+        let mut last_cost = best_cost;
+        for _ in 0..ULTRA_ITERATIONS {
+            let symbols = self.optimal_parse(start, &match_starts, &matches, &tables.lit_lens, &tables.dist_lens);
+            let stats = SymbolStats::new(&symbols);
+            tables = DynamicTables::new(&stats)?;
+            let cost = tables.cost(&stats);
+            if cost < best_cost {
+                best = symbols;
+                best_cost = cost;
+            }
+            else if cost == last_cost {
+                break;
+            }
+            last_cost = cost;
+        }
+        self.write_symbols(start, &best, last, strategy)
+    }
+}
+
+const HASH_BITS: u32 = 15;
+const HASH_SIZE: usize = 1 << HASH_BITS;
+const NO_POS: usize = usize::MAX;
+const ULTRA_ITERATIONS: usize = 15;
+
+const EOB_CODE: usize = 256;
+const CODE_LEN_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+const MAX_CODE_BITS: usize = 15;
+const MAX_CODE_LEN_BITS: usize = 7;
+
+// Everything needed to write a dynamic Huffman block for one symbol stream
+struct DynamicTables {
+    lit_lens: Vec<usize>,
+    lit_codes: Vec<usize>,
+    dist_lens: Vec<usize>,
+    dist_codes: Vec<usize>,
+    cl_lens: Vec<usize>,
+    cl_codes: Vec<usize>,
+    code_len_symbols: Vec<(usize, usize)>,
+    hlit: usize,
+    hdist: usize,
+    hclen: usize,
+}
 
-    fn is_long_match(&mut self, lookup: &Vec<usize>, curr_match: (Vec<u8>, usize, usize)) -> (Vec<u8>, usize, usize){
-        let mut curr_match_new = curr_match.clone();
-        if lookup.len() > 1 {
-            if self.data_pos - lookup[1] < 32768 {
-                curr_match_new = (curr_match.0, 3, self.data_pos - lookup[1]);
+impl DynamicTables {
+    fn new(stats: &SymbolStats) -> io::Result<Self> {
+        let mut lit_freqs = stats.lit_freqs.clone();
+        let mut dist_freqs = stats.dist_freqs.clone();
+        lit_freqs[EOB_CODE] += 1;
+        pad_frequencies(&mut lit_freqs);
+        pad_frequencies(&mut dist_freqs);
+
+        let lit_lens = huffman_code_lengths(&lit_freqs, MAX_CODE_BITS)?;
+        let dist_lens = huffman_code_lengths(&dist_freqs, MAX_CODE_BITS)?;
+        let hlit = (257..=286).rev().find(|n| lit_lens[n - 1] != 0).unwrap_or(257);
+        let hdist = (1..=30).rev().find(|n| dist_lens[n - 1] != 0).unwrap_or(1);
+
+        // Literal/length and distance code lengths are sent as one run-length encoded sequence
+        let mut all_lens: Vec<usize> = lit_lens[..hlit].to_vec();
+        all_lens.extend(&dist_lens[..hdist]);
+        let code_len_symbols = run_length_encode(&all_lens);
+        let mut cl_freqs: Vec<usize> = vec![0; 19];
+        for (symbol, _) in code_len_symbols.iter() {
+            cl_freqs[*symbol] += 1;
+        }
+        pad_frequencies(&mut cl_freqs);
+        let cl_lens = huffman_code_lengths(&cl_freqs, MAX_CODE_LEN_BITS)?;
+        let hclen = (4..=19).rev().find(|n| cl_lens[CODE_LEN_ORDER[n - 1]] != 0).unwrap_or(4);
+
+        Ok(DynamicTables {
+            lit_codes: canonical_codes(&lit_lens),
+            dist_codes: canonical_codes(&dist_lens),
+            cl_codes: canonical_codes(&cl_lens),
+            lit_lens,
+            dist_lens,
+            cl_lens,
+            code_len_symbols,
+            hlit,
+            hdist,
+            hclen,
+        })
+    }
+
+    // Size in bits of the block these tables would produce, header included
+    fn cost(&self, stats: &SymbolStats) -> usize {
+        let mut bits = 3 + 5 + 5 + 4 + 3 * self.hclen;
+        for (symbol, _) in self.code_len_symbols.iter().copied() {
+            bits += self.cl_lens[symbol] + code_len_extra(symbol).0;
+        }
+        bits += stats.lit_freqs.iter().zip(&self.lit_lens).map(|(freq, len)| freq * len).sum::<usize>();
+        bits += stats.dist_freqs.iter().zip(&self.dist_lens).map(|(freq, len)| freq * len).sum::<usize>();
+        bits + stats.extra_bits + self.lit_lens[EOB_CODE]
+    }
+}
+
+// Symbol counts for a stretch of the lz77 output, which is all the block costs depend on
+#[derive(Clone)]
+struct SymbolStats {
+    lit_freqs: Vec<usize>,
+    dist_freqs: Vec<usize>,
+    // Length and distance extra bits, which cost the same whatever the codes
+    extra_bits: usize,
+    // Input bytes the symbols stand for
+    len: usize,
+}
+
+impl SymbolStats {
+    fn new(symbols: &[(usize, usize)]) -> Self {
+        let mut stats = SymbolStats { lit_freqs: vec![0; 286], dist_freqs: vec![0; 30], extra_bits: 0, len: 0 };
+        for (cur_val, dist_back) in symbols.iter().copied() {
+            if dist_back == 0 {
+                stats.lit_freqs[cur_val] += 1;
+                stats.len += 1;
+                continue;
             }
+            let (len_code, len_extra, _) = length_code(cur_val);
+            let (dist_code, dist_extra, _) = distance_code(dist_back);
+            stats.lit_freqs[len_code] += 1;
+            stats.dist_freqs[dist_code] += 1;
+            stats.extra_bits += len_extra + dist_extra;
+            stats.len += cur_val;
         }
-        let mut out = curr_match_new.clone();
-        for (i, idx) in lookup.iter().enumerate().skip(1){
-            let mut prev = (Vec::new(), 1, 1);
-            let mut buffer = curr_match_new.0.clone();
-            if self.data_pos==26{
-                println!("curr_match_new: {:?}, out: {:?}", curr_match_new, out);
+        stats
+    }
+
+    fn add(&mut self, other: &SymbolStats) {
+        self.lit_freqs.iter_mut().zip(&other.lit_freqs).for_each(|(freq, other)| *freq += other);
+        self.dist_freqs.iter_mut().zip(&other.dist_freqs).for_each(|(freq, other)| *freq += other);
+        self.extra_bits += other.extra_bits;
+        self.len += other.len;
+    }
+
+    // Size in bits of the cheapest block for these symbols
+    fn block_cost(&self) -> io::Result<usize> {
+        let dynamic = DynamicTables::new(self)?.cost(self);
+        Ok(dynamic.min(fixed_cost(self)).min(stored_cost(self.len)))
+    }
+}
+
+fn fixed_code_len(symbol: usize) -> usize {
+    match symbol {
+        0..=143 => 8,
+        144..=255 => 9,
+        256..=279 => 7,
+        _ => 8,
+    }
+}
+
+// Size in bits of the block the fixed codes would produce
+fn fixed_cost(stats: &SymbolStats) -> usize {
+    let lit_bits: usize = stats.lit_freqs.iter().enumerate().map(|(symbol, freq)| freq * fixed_code_len(symbol)).sum();
+    let dist_bits: usize = stats.dist_freqs.iter().map(|freq| freq * 5).sum();
+    3 + lit_bits + dist_bits + stats.extra_bits + fixed_code_len(EOB_CODE)
+}
+
+// Size in bits of len bytes as stored blocks, counting a typical pad to the byte boundary
+fn stored_cost(len: usize) -> usize {
+    let blocks = len.div_ceil(65535).max(1);
+    blocks * (3 + 5 + 32) + 8 * len
+}
+
+const SPLIT_CANDIDATES: usize = 16;
+const MIN_SPLIT_SYMBOLS: usize = 1024;
+
+// Splits symbols[from..to] at whichever of a few evenly spaced points makes the two halves cheapest,
+// as long as that beats keeping them together, then tries again on each half. Cut points go into bounds in order.
+fn split_symbols(symbols: &[(usize, usize)], from: usize, to: usize, bounds: &mut Vec<usize>) -> io::Result<()> {
+    if to - from < 2 * MIN_SPLIT_SYMBOLS {
+        return Ok(());
+    }
+    let cut = |k: usize| from + (to - from) * k / SPLIT_CANDIDATES;
+    // Counting each stretch between candidates once lets every split be priced from sums of them
+    let pieces: Vec<SymbolStats> = (0..SPLIT_CANDIDATES).map(|k| SymbolStats::new(&symbols[cut(k)..cut(k + 1)])).collect();
+    let mut whole = pieces[0].clone();
+    pieces[1..].iter().for_each(|piece| whole.add(piece));
+    let mut best = (whole.block_cost()?, None);
+    let mut left = pieces[0].clone();
+    for k in 1..SPLIT_CANDIDATES {
+        let mid = cut(k);
+        if mid - from >= MIN_SPLIT_SYMBOLS && to - mid >= MIN_SPLIT_SYMBOLS {
+            let mut right = pieces[k].clone();
+            pieces[k + 1..].iter().for_each(|piece| right.add(piece));
+            let cost = left.block_cost()? + right.block_cost()?;
+            if cost < best.0 {
+                best = (cost, Some(mid));
             }
-            while prev.1 != curr_match_new.1 {
-                prev = curr_match_new.clone();
-                if self.data_pos - idx < 32768 && buffer.len() < 258{
-                    if self.data_pos + buffer.len() < self.data.len(){
-                        buffer.push(self.read_next_from(self.data_pos + buffer.len()));
-                        let comp = Vec::from(&self.data[*idx..(idx + buffer.len())]);
-                        if buffer.eq(&comp) {
-                            if buffer.len() > 258 {
-                                println!("Literally how the fuck");
-                            }
-                            curr_match_new = (buffer.clone(), buffer.len(), self.data_pos - *idx);
-                            out = curr_match_new.clone();
-                        }
-                        else {
-                            curr_match_new = prev.clone();
-                        }
-                    }
-                }
+        }
+        left.add(&pieces[k]);
+    }
+    if let Some(mid) = best.1 {
+        split_symbols(symbols, from, mid, bounds)?;
+        bounds.push(mid);
+        split_symbols(symbols, mid, to, bounds)?;
+    }
+    Ok(())
+}
+
+// Returns (extra bit count, repeat base) for a code length symbol
+fn code_len_extra(symbol: usize) -> (usize, usize) {
+    match symbol {
+        16 => (2, 3),
+        17 => (3, 3),
+        18 => (7, 11),
+        _ => (0, 0),
+    }
+}
+
+const LENGTH_BASES: [usize; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA_BITS: [usize; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASES: [usize; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA_BITS: [usize; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// Returns (length code, extra bit count, extra bit value) for a match length of 3..=258
+fn length_code(length: usize) -> (usize, usize, usize) {
+    let idx = LENGTH_BASES.iter().rposition(|base| *base <= length).unwrap();
+    (idx + 257, LENGTH_EXTRA_BITS[idx], length - LENGTH_BASES[idx])
+}
+
+// Returns (distance code, extra bit count, extra bit value) for a distance of 1..=32768
+fn distance_code(distance: usize) -> (usize, usize, usize) {
+    let idx = DISTANCE_BASES.iter().rposition(|base| *base <= distance).unwrap();
+    (idx, DISTANCE_EXTRA_BITS[idx], distance - DISTANCE_BASES[idx])
+}
+
+// DEFLATE wants at least two codes in a tree, so pad out sparse alphabets with unused symbols
+fn pad_frequencies(freqs: &mut [usize]) {
+    let mut used = freqs.iter().filter(|freq| **freq != 0).count();
+    for freq in freqs.iter_mut() {
+        if used >= 2 {
+            break;
+        }
+        if *freq == 0 {
+            *freq = 1;
+            used += 1;
+        }
+    }
+}
+
+// Compresses a run of code lengths into (code length symbol, repeat count) pairs using symbols 16, 17 and 18
+fn run_length_encode(lens: &[usize]) -> Vec<(usize, usize)> {
+    let mut out: Vec<(usize, usize)> = Vec::new();
+    let mut i = 0;
+    while i < lens.len() {
+        let len = lens[i];
+        let mut run = 1;
+        while i + run < lens.len() && lens[i + run] == len {
+            run += 1;
+        }
+        i += run;
+        if len == 0 {
+            while run >= 11 {
+                let repeat = run.min(138);
+                out.push((18, repeat));
+                run -= repeat;
             }
-            if self.data_pos==26{
-                println!("curr_match_new: {:?}, out: {:?}", curr_match_new, out);
+            if run >= 3 {
+                out.push((17, run));
+                run = 0;
             }
         }
-        return out;
+        else {
+            out.push((len, 0));
+            run -= 1;
+            while run >= 3 {
+                let repeat = run.min(6);
+                out.push((16, repeat));
+                run -= repeat;
+            }
+        }
+        for _ in 0..run {
+            out.push((len, 0));
+        }
     }
+    out
 }
 
-pub fn deflate(args: Vec<String>) {
-    let file_name: String = args[1].to_string();
-    let mut file = File::open(&file_name).expect("No File Found");
-    let mut fdata: Vec<u8> = Vec::new();
-    file.read_to_end(&mut fdata).expect("Unable to read data");
-    let deflated_data: Vec<u8> = deflate_data(fdata);
-    let mut new_name = file_name;
-    
+#[derive(Debug, Clone, Copy)]
+pub struct CompressOptions {
+    // 0 stores the input as is, 1 through 9 trade speed for smaller output
+    pub level: u8,
+    // Replaces the lz77 parse with repeated optimal parsing against the block's own Huffman codes.
+    // Many times slower, for output that is written once and read often; the level still sets the match search.
+    pub ultra: bool,
+    // Overrides how many earlier positions the level's match search tries at each step
+    pub max_chain: Option<usize>,
+}
+
+impl Default for CompressOptions {
+    fn default() -> Self {
+        CompressOptions { level: 6, ultra: false, max_chain: None }
+    }
+}
+
+impl CompressOptions {
+    fn config(&self) -> &'static LevelConfig {
+        &LEVELS[self.level.min(9) as usize]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BlockStrategy {
+    Stored,
+    Fixed,
+    // Whichever of the fixed and dynamic codes comes out smaller
+    Cheapest,
+}
+
+struct LevelConfig {
+    // How many earlier positions with the same hash to try before settling on the best match so far
+    max_chain: usize,
+    // A match at least this long ends the search early
+    nice_length: usize,
+    // Once a match is this long, checking the next position for a better one only walks a quarter of the chain
+    good_length: usize,
+    // Matches at least this long are taken as they are, without checking the next position at all
+    max_lazy: usize,
+    strategy: BlockStrategy,
+}
+
+const LEVELS: [LevelConfig; 10] = [
+    LevelConfig { max_chain: 0, nice_length: 0, good_length: 0, max_lazy: 0, strategy: BlockStrategy::Stored },
+    LevelConfig { max_chain: 4, nice_length: 8, good_length: 0, max_lazy: 0, strategy: BlockStrategy::Fixed },
+    LevelConfig { max_chain: 8, nice_length: 16, good_length: 0, max_lazy: 0, strategy: BlockStrategy::Fixed },
+    LevelConfig { max_chain: 16, nice_length: 32, good_length: 0, max_lazy: 0, strategy: BlockStrategy::Cheapest },
+    LevelConfig { max_chain: 16, nice_length: 16, good_length: 4, max_lazy: 4, strategy: BlockStrategy::Cheapest },
+    LevelConfig { max_chain: 32, nice_length: 32, good_length: 8, max_lazy: 16, strategy: BlockStrategy::Cheapest },
+    LevelConfig { max_chain: 128, nice_length: 128, good_length: 8, max_lazy: 16, strategy: BlockStrategy::Cheapest },
+    LevelConfig { max_chain: 256, nice_length: 128, good_length: 8, max_lazy: 32, strategy: BlockStrategy::Cheapest },
+    LevelConfig { max_chain: 1024, nice_length: 258, good_length: 32, max_lazy: 128, strategy: BlockStrategy::Cheapest },
+    LevelConfig { max_chain: 4096, nice_length: 258, good_length: 32, max_lazy: 258, strategy: BlockStrategy::Cheapest },
+];
+
+// Every byte as a fixed Huffman literal, with no match search at all
+const LITERALS_ONLY: LevelConfig = LevelConfig { max_chain: 0, nice_length: 0, good_length: 0, max_lazy: 0, strategy: BlockStrategy::Fixed };
+
+const WINDOW_SIZE: usize = 32768;
+const BLOCK_SIZE: usize = 65536;
+
+// Compresses everything written to it into a raw deflate stream, 64K of input at a time.
+// Only the last 32K of input is kept for matches; nothing is final until finish() is called.
+pub struct DeflateEncoder<W: Write> {
+    deflator: BitStreamDeflator<W>,
+    strategy: BlockStrategy,
+    ultra: bool,
+    // Bytes at the front of deflator.data that earlier blocks already covered
+    history_len: usize,
+}
+
+impl<W: Write> DeflateEncoder<W> {
+    pub fn new(writer: W, options: &CompressOptions) -> Self {
+        let mut encoder = Self::with_config(writer, options.config());
+        encoder.ultra = options.ultra;
+        if let Some(max_chain) = options.max_chain {
+            encoder.deflator.max_chain = max_chain;
+        }
+        encoder
+    }
+
+    fn with_config(writer: W, config: &LevelConfig) -> Self {
+        DeflateEncoder { deflator: BitStreamDeflator::new(Vec::new(), writer, config), strategy: config.strategy, ultra: false, history_len: 0 }
+    }
+
+    fn write_block(&mut self, last: bool) -> io::Result<()> {
+        if self.strategy == BlockStrategy::Stored {
+            self.deflator.data_pos = self.history_len;
+            let end = self.deflator.data.len();
+            self.deflator.write_stored(end, last)?;
+        }
+        else if self.ultra {
+            self.deflator.write_ultra(self.history_len, last, self.strategy)?;
+        }
+        else {
+            self.deflator.lz77_baseline(self.history_len);
+            self.deflator.write_huffman_with_lz77(last, self.strategy)?;
+        }
+        let data = &mut self.deflator.data;
+        let keep = data.len().min(WINDOW_SIZE);
+        data.drain(..data.len() - keep);
+        self.history_len = keep;
+        Ok(())
+    }
+
+    // Writes out any buffered input as the final block and hands back the underlying writer
+    pub fn finish(mut self) -> Result<W> {
+        self.write_block(true)?;
+        Ok(self.deflator.writer.finish()?)
+    }
+}
+
+impl<W: Write> Write for DeflateEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let pending = self.deflator.data.len() - self.history_len;
+        let len = buf.len().min(BLOCK_SIZE - pending);
+        self.deflator.data.extend_from_slice(&buf[..len]);
+        if pending + len == BLOCK_SIZE {
+            self.write_block(false)?;
+        }
+        Ok(len)
+    }
+
+    // Ends the current block and pads to a byte boundary with an empty stored block, so everything
+    // written so far can be decoded from what has reached the inner writer
+    fn flush(&mut self) -> io::Result<()> {
+        if self.deflator.data.len() > self.history_len {
+            self.write_block(false)?;
+        }
+        let writer = &mut self.deflator.writer;
+        writer.write(0, 3)?;
+        writer.align_to_byte()?;
+        writer.write(0, 16)?;
+        writer.write(0xffff, 16)?;
+        writer.flush()
+    }
+}
+
+pub fn compress(data: &[u8], options: &CompressOptions) -> Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), options);
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+// Compresses file_name into file_name.deflate
+pub fn deflate_file(file_name: &str, options: &CompressOptions) -> Result<()> {
+    let mut file = BufReader::new(File::open(file_name)?);
+    let mut new_name = file_name.to_string();
     new_name.push_str(".deflate");
-    let mut new_file = File::create(&new_name).expect("Unable to create file");
-    new_file.write_all(&deflated_data);
+    let mut encoder = DeflateEncoder::new(BufWriter::new(File::create(&new_name)?), options);
+    io::copy(&mut file, &mut encoder)?;
+    encoder.finish()?.flush()?;
+    Ok(())
 }
 
-pub fn deflate_data(data: Vec<u8>) -> Vec<u8>{
-    let mut bs: BitStreamDeflator = BitStreamDeflator::new(data);
-    bs.write_huffman();
-    return bs.look_back_buffer;
+pub fn deflate_data(data: Vec<u8>) -> Result<Vec<u8>>{
+    let mut encoder = DeflateEncoder::with_config(Vec::new(), &LITERALS_ONLY);
+    encoder.write_all(&data)?;
+    encoder.finish()
 }
 
-pub fn lz(args: Vec<String>){
-    let file_name: String = args[1].to_string();
-    let mut file = File::open(&file_name).expect("No File Found");
+// Writes the lz77 parse of file_name to file_name.lz77 in a readable <length,distance> form
+pub fn lz77_file(file_name: &str) -> Result<()> {
+    let mut file = File::open(file_name)?;
     let mut fdata: Vec<u8> = Vec::new();
-    file.read_to_end(&mut fdata).expect("Unable to read data");
-    let mut bs: BitStreamDeflator = BitStreamDeflator::new(fdata);
-    bs.lz77_baseline();
+    file.read_to_end(&mut fdata)?;
+    let mut bs = BitStreamDeflator::new(fdata, io::sink(), CompressOptions::default().config());
+    bs.lz77_baseline(0);
     let mut lz_data: Vec<u8> = Vec::new();
     for i in bs.lz77_output.iter() {
         lz_data.push(*i as u8);
     }
-    let mut new_name = file_name;
+    let mut new_name = file_name.to_string();
     new_name.push_str(".lz77");
-    let mut new_file = File::create(&new_name).expect("Unable to create file");
-    new_file.write_all(&lz_data);
+    let mut new_file = File::create(&new_name)?;
+    new_file.write_all(&lz_data)?;
+    Ok(())
 }
 
-pub fn deflate_with_77(args: Vec<String>) {
-    let file_name: String = args[1].to_string();
-    let mut file = File::open(&file_name).expect("No File Found");
-    let mut fdata: Vec<u8> = Vec::new();
-    file.read_to_end(&mut fdata).expect("Unable to read data");
-    let deflated_data: Vec<u8> = deflate_data_with_77(fdata);
-    
-    let mut new_name = file_name;
-    
-    new_name.push_str(".deflate");
-    let mut new_file = File::create(&new_name).expect("Unable to create file");
-    new_file.write_all(&deflated_data);
+pub fn deflate_data_with_77(data: Vec<u8>) -> Result<Vec<u8>>{
+    compress(&data, &CompressOptions::default())
 }
-
-pub fn deflate_data_with_77(data: Vec<u8>) -> Vec<u8>{
-    let mut bs: BitStreamDeflator = BitStreamDeflator::new(data);
-    bs.lz77_baseline();
-    bs.write_huffman_with_lz77();
-    return bs.look_back_buffer;
-}
\ No newline at end of file
@@ -0,0 +1,162 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// MS-DOS timestamps count from 1980 in two second steps and say nothing about the time zone. Like
+// Info-ZIP, they are written and read as local time; the _with_offset versions take an explicit
+// offset in seconds east of UTC instead, 0 for UTC.
+
+const SECS_PER_DAY: u64 = 86400;
+// 1980-01-01 00:00:00, the earliest time the format can hold
+pub const DOS_EPOCH: (u16, u16) = (0, (1 << 5) | 1);
+// 2107-12-31 23:59:58, the latest
+const DOS_END: (u16, u16) = ((23 << 11) | (59 << 5) | 29, (127 << 9) | (12 << 5) | 31);
+
+// Returns (time, date) for a zip record, clamping anything outside 1980..=2107 to the nearest end
+pub fn dos_datetime(time: SystemTime) -> (u16, u16) {
+    dos_datetime_with_offset(time, local_offset(time))
+}
+
+pub fn dos_datetime_with_offset(time: SystemTime, offset: i64) -> (u16, u16) {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64 + offset,
+        Err(_) => return DOS_EPOCH,
+    };
+    if secs < 0 {
+        return DOS_EPOCH;
+    }
+    let secs = secs as u64;
+    let (year, month, day) = civil_from_days(secs / SECS_PER_DAY);
+    if year < 1980 {
+        return DOS_EPOCH;
+    }
+    if year > 2107 {
+        return DOS_END;
+    }
+    let secs_of_day = secs % SECS_PER_DAY;
+    let time = ((secs_of_day / 3600) << 11) | ((secs_of_day % 3600 / 60) << 5) | (secs_of_day % 60 / 2);
+    let date = ((year - 1980) << 9) | (month << 5) | day;
+    (time as u16, date as u16)
+}
+
+// None for fields that don't name a real time, such as the all-zero stamps older archives carry
+pub fn system_time_from_dos(time: u16, date: u16) -> Option<SystemTime> {
+    let as_utc = system_time_from_dos_with_offset(time, date, 0)?;
+    // The offset belongs to the instant being looked for, so it is taken once at a first guess and
+    // again at the corrected time, which settles stamps close to a daylight saving change
+    let guess = shift(as_utc, -local_offset(as_utc));
+    Some(shift(as_utc, -local_offset(guess)))
+}
+
+pub fn system_time_from_dos_with_offset(time: u16, date: u16, offset: i64) -> Option<SystemTime> {
+    let (year, month, day) = (1980 + (date >> 9) as u64, ((date >> 5) & 0xf) as u64, (date & 0x1f) as u64);
+    let (hour, minute, second) = ((time >> 11) as u64, ((time >> 5) & 0x3f) as u64, (time & 0x1f) as u64 * 2);
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let secs = days_from_civil(year, month, day) * SECS_PER_DAY + hour * 3600 + minute * 60 + second;
+    Some(shift(UNIX_EPOCH + Duration::from_secs(secs), -offset))
+}
+
+fn shift(time: SystemTime, secs: i64) -> SystemTime {
+    if secs >= 0 {
+        time + Duration::from_secs(secs as u64)
+    }
+    else {
+        time - Duration::from_secs(secs.unsigned_abs())
+    }
+}
+
+// Seconds east of UTC in the local time zone at the given time, honouring TZ
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn local_offset(time: SystemTime) -> i64 {
+    use std::os::raw::{c_char, c_int, c_long};
+    // struct tm as glibc and macOS lay it out, both with the BSD tm_gmtoff and tm_zone at the end
+    #[repr(C)]
+    struct Tm {
+        tm_sec: c_int,
+        tm_min: c_int,
+        tm_hour: c_int,
+        tm_mday: c_int,
+        tm_mon: c_int,
+        tm_year: c_int,
+        tm_wday: c_int,
+        tm_yday: c_int,
+        tm_isdst: c_int,
+        tm_gmtoff: c_long,
+        tm_zone: *const c_char,
+    }
+    extern "C" {
+        fn tzset();
+        fn localtime_r(time: *const c_long, result: *mut Tm) -> *mut Tm;
+    }
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs().min(c_long::MAX as u64) as c_long,
+        Err(before) => -(before.duration().as_secs().min(c_long::MAX as u64) as c_long),
+    };
+    // SAFETY: localtime_r only writes the struct it is given, and a zeroed Tm is a valid value
+    unsafe {
+        tzset();
+        let mut tm: Tm = std::mem::zeroed();
+        if localtime_r(&secs, &mut tm).is_null() {
+            return 0;
+        }
+        tm.tm_gmtoff as i64
+    }
+}
+
+// Elsewhere there is no offset to be had without a platform crate, so times stay in UTC
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn local_offset(_time: SystemTime) -> i64 {
+    0
+}
+
+// Whole seconds either side of 1970 as the 32-bit count the UT extra field holds, clamped to fit
+pub fn unix_seconds(time: SystemTime) -> i32 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs().min(i32::MAX as u64) as i32,
+        Err(before) => -(before.duration().as_secs().min(i32::MAX as u64 + 1) as i64) as i32,
+    }
+}
+
+pub fn system_time_from_unix(secs: i32) -> SystemTime {
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64)
+    }
+    else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs() as u64)
+    }
+}
+
+fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 to (year, month, day), counting in 400 year eras that start on March 1st
+// so the leap day falls at the end of each year
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = era * 400 + year_of_era + (month <= 2) as u64;
+    (year, month, day)
+}
+
+// The inverse of civil_from_days, for years from 1970 on
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    BadSignature { expected: u32, found: u32 },
+    InvalidArchive(String),
+    CorruptDeflate { bit_offset: u64, message: String },
+    UnsupportedMethod(u16),
+    CrcMismatch { name: String, expected: u32, found: u32 },
+    InvalidDistance { bit_offset: u64, distance: usize, available: usize },
+    InvalidName(Vec<u8>),
+    UnsafeSymlink { name: String, target: String },
+    UnsafePath(String),
+    TooLarge(String),
+    NoSuchEntry { index: usize, len: usize },
+    TooManySymbols { symbols: usize, max_len: usize },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::BadSignature { expected, found } => write!(f, "Bad record signature {:08x}, expected {:08x}", found, expected),
+            Error::InvalidArchive(message) => write!(f, "Invalid archive: {}", message),
+            Error::CorruptDeflate { bit_offset, message } => write!(f, "Corrupt deflate stream at bit {}: {}", bit_offset, message),
+            Error::UnsupportedMethod(method) => write!(f, "Unsupported compression method {}", method),
+            Error::CrcMismatch { name, expected, found } => write!(f, "CRC mismatch for {}: expected {:08x}, got {:08x}", name, expected, found),
+            Error::InvalidDistance { bit_offset, distance, available } => {
+                write!(f, "Distance {} at bit {} reaches back past the {} bytes written so far", distance, bit_offset, available)
+            }
+            Error::InvalidName(name) => write!(f, "Invalid entry name {}", String::from_utf8_lossy(name)),
+            Error::UnsafeSymlink { name, target } => write!(f, "Refusing to create {}: link target {} is outside the destination", name, target),
+            Error::UnsafePath(name) => write!(f, "Skipped {}: the path leads outside the destination", name),
+            Error::TooLarge(message) => write!(f, "Too large for a zip archive without Zip64: {}", message),
+            Error::NoSuchEntry { index, len } => write!(f, "No entry {} in an archive of {} entries", index, len),
+            Error::TooManySymbols { symbols, max_len } => write!(f, "{} symbols can't be coded in {} bits", symbols, max_len),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// Errors that had to travel through a Read or Write impl come back out as themselves
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        if err.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            return *err.into_inner().unwrap().downcast::<Error>().unwrap();
+        }
+        Error::Io(err)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+// Length-limited Huffman codes built with the package-merge algorithm, and table-driven decoding for them
+use super::helpers::*;
+use super::error::*;
+
+#[derive(Clone, Copy)]
+enum Item {
+    Leaf(usize),
+    // Indices of the two items, one level deeper, that were packaged together
+    Package(usize, usize),
+}
+
+// Returns optimal code lengths for the given symbol frequencies with no code longer than max_len bits.
+// Unused symbols get length 0, and a lone used symbol gets a 1 bit code.
+pub fn huffman_code_lengths(freqs: &[usize], max_len: usize) -> Result<Vec<usize>> {
+    let mut lens: Vec<usize> = vec![0; freqs.len()];
+    let mut leaves: Vec<(usize, usize)> = freqs.iter().copied().enumerate()
+        .filter(|(_, freq)| *freq != 0)
+        .map(|(symbol, freq)| (freq, symbol))
+        .collect();
+    if leaves.len() <= 1 {
+        for (_, symbol) in leaves {
+            lens[symbol] = 1;
+        }
+        return Ok(lens);
+    }
+    if max_len < usize::BITS as usize && leaves.len() > 1 << max_len {
+        return Err(Error::TooManySymbols { symbols: leaves.len(), max_len });
+    }
+    leaves.sort();
+
+    // levels[0] is the deepest list (only leaves), levels[max_len - 1] the shallowest
+    let mut levels: Vec<Vec<(usize, Item)>> = Vec::new();
+    levels.push(leaves.iter().map(|(freq, symbol)| (*freq, Item::Leaf(*symbol))).collect());
+    for _ in 1..max_len {
+        let prev = levels.last().unwrap();
+        let mut merged: Vec<(usize, Item)> = Vec::with_capacity(leaves.len() + prev.len() / 2);
+        let mut leaf_idx = 0;
+        let mut pair_idx = 0;
+        while leaf_idx < leaves.len() || pair_idx + 1 < prev.len() {
+            let package_weight = if pair_idx + 1 < prev.len() { Some(prev[pair_idx].0 + prev[pair_idx + 1].0) } else { None };
+            match package_weight {
+                Some(weight) if leaf_idx >= leaves.len() || weight < leaves[leaf_idx].0 => {
+                    merged.push((weight, Item::Package(pair_idx, pair_idx + 1)));
+                    pair_idx += 2;
+                }
+                _ => {
+                    merged.push((leaves[leaf_idx].0, Item::Leaf(leaves[leaf_idx].1)));
+                    leaf_idx += 1;
+                }
+            }
+        }
+        levels.push(merged);
+    }
+
+    // The cheapest 2n - 2 items of the top list make up the code; every leaf they reach adds a bit to its symbol
+    let mut selected: Vec<usize> = (0..2 * leaves.len() - 2).collect();
+    for level in levels.iter().rev() {
+        let mut next_selected: Vec<usize> = Vec::new();
+        for idx in selected {
+            match level[idx].1 {
+                Item::Leaf(symbol) => lens[symbol] += 1,
+                Item::Package(a, b) => {
+                    next_selected.push(a);
+                    next_selected.push(b);
+                }
+            }
+        }
+        selected = next_selected;
+    }
+    Ok(lens)
+}
+
+// Assigns canonical codes from code lengths as in RFC 1951 section 3.2.2
+pub fn canonical_codes(lens: &[usize]) -> Vec<usize> {
+    let max_len = lens.iter().copied().max().unwrap_or(0);
+    let mut bl_count: Vec<usize> = vec![0; max_len + 1];
+    for len in lens.iter() {
+        if *len != 0 {
+            bl_count[*len] += 1;
+        }
+    }
+    let mut next_code: Vec<usize> = vec![0; max_len + 1];
+    let mut code = 0;
+    for bits in 1..=max_len {
+        code = (code + bl_count[bits - 1]) << 1;
+        next_code[bits] = code;
+    }
+    let mut codes: Vec<usize> = vec![0; lens.len()];
+    for (symbol, len) in lens.iter().enumerate() {
+        if *len != 0 {
+            codes[symbol] = next_code[*len];
+            next_code[*len] += 1;
+        }
+    }
+    codes
+}
+
+#[derive(Clone, Copy)]
+enum DecodeEntry {
+    Invalid,
+    // (symbol, code length)
+    Symbol(u16, u8),
+    // (offset of the sub-table, number of bits it is indexed by)
+    SubTable(u32, u8),
+}
+
+// Decodes canonical Huffman codes by looking up the next primary_bits bits of the stream,
+// falling through to a sub-table for the rare codes that are longer than that
+pub struct DecodeTable {
+    primary_bits: usize,
+    max_len: usize,
+    entries: Vec<DecodeEntry>,
+}
+
+impl DecodeTable {
+    // Returns None when the lengths describe an over-subscribed (impossible) code
+    pub fn new(lens: &[usize], primary_bits: usize) -> Option<Self> {
+        let max_len = lens.iter().copied().max().unwrap_or(0);
+        let kraft: usize = lens.iter().filter(|len| **len != 0).map(|len| 1 << (max_len - len)).sum();
+        if kraft > 1 << max_len {
+            return None;
+        }
+        let primary_bits = primary_bits.min(max_len);
+        let codes = canonical_codes(lens);
+        let mut entries: Vec<DecodeEntry> = vec![DecodeEntry::Invalid; 1 << primary_bits];
+        let primary_mask = (1 << primary_bits) - 1;
+
+        // Size each sub-table for the longest code sharing its primary prefix
+        let mut sub_bits: Vec<usize> = vec![0; 1 << primary_bits];
+        for (symbol, len) in lens.iter().copied().enumerate() {
+            if len > primary_bits {
+                let prefix = reverse_bits(codes[symbol], len) & primary_mask;
+                sub_bits[prefix] = sub_bits[prefix].max(len - primary_bits);
+            }
+        }
+        for (prefix, bits) in sub_bits.iter().copied().enumerate() {
+            if bits != 0 {
+                entries[prefix] = DecodeEntry::SubTable(entries.len() as u32, bits as u8);
+                entries.extend(vec![DecodeEntry::Invalid; 1 << bits]);
+            }
+        }
+
+        for (symbol, len) in lens.iter().copied().enumerate() {
+            if len == 0 {
+                continue;
+            }
+            // Codes arrive most significant bit first, so the peeked bits hold them reversed
+            let reversed = reverse_bits(codes[symbol], len);
+            let entry = DecodeEntry::Symbol(symbol as u16, len as u8);
+            if len <= primary_bits {
+                for fill in 0..1 << (primary_bits - len) {
+                    entries[reversed | (fill << len)] = entry;
+                }
+            }
+            else if let DecodeEntry::SubTable(offset, bits) = entries[reversed & primary_mask] {
+                let sub_len = len - primary_bits;
+                for fill in 0..1 << (bits as usize - sub_len) {
+                    entries[offset as usize + ((reversed >> primary_bits) | (fill << sub_len))] = entry;
+                }
+            }
+        }
+        Some(DecodeTable { primary_bits, max_len, entries })
+    }
+
+    // The most bits decode could need to look at
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    // Looks up the code at the start of bits (first stream bit in bit 0), returning (symbol, code length)
+    pub fn decode(&self, bits: usize) -> Option<(usize, usize)> {
+        let mut entry = self.entries[bits & ((1 << self.primary_bits) - 1)];
+        if let DecodeEntry::SubTable(offset, sub_bits) = entry {
+            let idx = (bits >> self.primary_bits) & ((1 << sub_bits) - 1);
+            entry = self.entries[offset as usize + idx];
+        }
+        match entry {
+            DecodeEntry::Symbol(symbol, len) => Some((symbol as usize, len as usize)),
+            _ => None,
+        }
+    }
+}
@@ -1,599 +1,318 @@
-use std::collections::HashMap;
 use std::fs::File;
-use std::io::Read;
+use std::io;
+use std::io::{BufReader, BufWriter, Read};
 use std::io::Write;
-use super::helpers::*;
+use super::huffman::*;
+use super::bitstream::*;
+use super::error::*;
+
+// Where decoding picks up next time more output is wanted
+enum BlockState {
+    Header,
+    Stored(usize),
+    Huffman(DecodeTable, DecodeTable),
+    Done,
+}
 
-struct BitStreamInflator {
-    data: Vec<u8>,
-    byte_pos: usize,
-    bit_pos: usize,
+struct BitStreamInflator<R: Read> {
+    reader: BitReader<R>,
     cur_block_type: usize,
+    state: BlockState,
     look_back_buffer: Vec<u8>,
     finished: bool
 }
 
 
-impl BitStreamInflator {
+impl<R: Read> BitStreamInflator<R> {
 
-    pub fn new(data: Vec<u8>) -> Self {
+    pub fn new(reader: R) -> Self {
         Self {
-            data,
-            byte_pos: 0,
-            bit_pos: 0,
+            reader: BitReader::new(reader),
             cur_block_type: 0,
+            state: BlockState::Header,
             look_back_buffer: Vec::new(),
             finished: false
         }
     }
 
-    fn get_next_bit(&mut self) -> bool{
-        let ret = self.data[self.byte_pos] & (1 << self.bit_pos) != 0;
-        self.bit_pos += 1;
-        if self.bit_pos >= 8 {
-            self.byte_pos += 1;
-            self.bit_pos = 0;
-        }
-        return ret
+    fn corrupt(&self, message: String) -> Error {
+        Error::CorruptDeflate { bit_offset: self.reader.position(), message }
     }
 
-    fn read_ahead(&mut self, n: usize) -> Vec<bool> {
-        let mut ret: Vec<bool> = Vec::new();
-        for _ in 0..n {
-            ret.push(self.get_next_bit());
-        }
-        return ret;
+    fn read_symbol(&mut self, table: &DecodeTable) -> Result<usize> {
+        let bits = self.reader.peek(table.max_len())?;
+        let (symbol, len) = table.decode(bits).ok_or_else(|| self.corrupt("Invalid Huffman code".to_string()))?;
+        self.reader.consume(len)?;
+        Ok(symbol)
     }
 
-    fn block_start(&mut self) {
-        let bfinal = self.get_next_bit();
-        if bfinal {
+    fn block_start(&mut self) -> Result<()> {
+        let bfinal = self.reader.read_bits(1)?;
+        if bfinal == 1 {
             self.finished = true;
         }
+        Ok(())
     }
 
-    fn block_type(&mut self) {
-        let mut type_bin = self.read_ahead(2);
-        type_bin.reverse();
-        self.cur_block_type = binary_to_dec(&type_bin) 
+    fn block_type(&mut self) -> Result<()> {
+        self.cur_block_type = self.reader.read_bits(2)?;
+        Ok(())
     }
 
-    fn block_read(&mut self) {
-        self.block_start();
-        self.block_type();
+    fn block_read(&mut self) -> Result<()> {
+        self.block_start()?;
+        self.block_type()?;
         
-        match self.cur_block_type {
-            0 => {self.read_no_compression()}
+        self.state = match self.cur_block_type {
+            0 => {self.read_no_compression()?}
             1 => {self.read_fixed_huffman()}
-            2 => {self.read_dynamic_huffman()}
-            _ => {}
+            2 => {self.read_dynamic_huffman()?}
+            _ => {return Err(self.corrupt("Invalid block type 3".to_string()))}
+        };
+        Ok(())
+    }
+
+    fn read_no_compression(&mut self) -> Result<BlockState> {
+        // Stored blocks skip to the next byte boundary before LEN and NLEN
+        self.reader.align_to_byte();
+        let len = self.reader.read_bits(16)? as u16;
+        let nlen = self.reader.read_bits(16)? as u16;
+        if nlen != !len {
+            return Err(self.corrupt(format!("Stored block length {:04x} does not match its complement {:04x}", len, nlen)));
         }
+        Ok(BlockState::Stored(len as usize))
     }
 
-    // TODO Add functionality if we want
-    fn read_no_compression(&mut self) {}
+    // Copies up to limit bytes of a stored block, returning how many are left
+    fn copy_stored(&mut self, remaining: usize, limit: usize) -> Result<usize> {
+        let len = remaining.min(limit.saturating_sub(self.look_back_buffer.len()).max(1));
+        let start = self.look_back_buffer.len();
+        self.look_back_buffer.resize(start + len, 0);
+        self.reader.read_bytes(&mut self.look_back_buffer[start..])?;
+        Ok(remaining - len)
+    }
 
     const EOB_CODE: usize = 256;
+    const LIT_PRIMARY_BITS: usize = 9;
+    const DIST_PRIMARY_BITS: usize = 6;
+
+    fn read_fixed_huffman(&mut self) -> BlockState {
+        let mut lit_lens: Vec<usize> = vec![8; 288];
+        lit_lens[144..256].fill(9);
+        lit_lens[256..280].fill(7);
+        let dist_lens: Vec<usize> = vec![5; 30];
+        let lit_table = DecodeTable::new(&lit_lens, Self::LIT_PRIMARY_BITS).unwrap();
+        let dist_table = DecodeTable::new(&dist_lens, Self::DIST_PRIMARY_BITS).unwrap();
+        BlockState::Huffman(lit_table, dist_table)
+    }
 
-    const MATCH_7_LOW: usize = 0;
-    const MATCH_7_HIGH: usize = 23;
-    const MATCH_8_1_LOW: usize = 48;
-    const MATCH_8_1_HIGH: usize = 191;
-    const MATCH_8_2_LOW: usize = 192;
-    const MATCH_8_2_HIGH: usize = 199;
-    const MATCH_9_LOW: usize = 400;
-    const MATCH_9_HIGH: usize = 511;
-
-    const BASE_7_CODE: usize = 256;
-    const BASE_8_1_CODE: usize = 0;
-    const BASE_8_2_CODE: usize = 280;
-    const BASE_9_CODE: usize = 144;
-
-
-    fn read_fixed_huffman(&mut self) {
-        let mut code = 0;
-
-        while code != BitStreamInflator::EOB_CODE {
-            let mut cur_chunk = self.read_ahead(6);
-            let mut found_match: bool = false;
-            while !found_match {
-                cur_chunk.push(self.get_next_bit());
-                let cur_val = binary_to_dec(&cur_chunk);
-                match cur_chunk.len() {
-                    7 => {
-                        if cur_val == BitStreamInflator::MATCH_7_LOW {
-                            code = cur_val - BitStreamInflator::MATCH_7_LOW + BitStreamInflator::BASE_7_CODE;
-                            found_match = true
-                        }
-                        // Length Case
-                        if BitStreamInflator::MATCH_7_LOW < cur_val && cur_val <= BitStreamInflator::MATCH_7_HIGH {
-                            code = cur_val - BitStreamInflator::MATCH_7_LOW + BitStreamInflator::BASE_7_CODE;
-                            self.repeat_buffer(code);
-                            found_match = true;
-                        } 
-                    }
-                    8 => {
-                        // Literal Case
-                        if BitStreamInflator::MATCH_8_1_LOW <= cur_val && cur_val <= BitStreamInflator::MATCH_8_1_HIGH {
-                            code = cur_val - BitStreamInflator::MATCH_8_1_LOW + BitStreamInflator::BASE_8_1_CODE;
-                            self.write_literal_code(code);
-                            found_match = true;
-                        } 
-
-                        // Length Case
-                        else if BitStreamInflator::MATCH_8_2_LOW <= cur_val && cur_val <= BitStreamInflator::MATCH_8_2_HIGH {
-                            code = cur_val - BitStreamInflator::MATCH_8_2_LOW + BitStreamInflator::BASE_8_2_CODE;
-                            self.repeat_buffer(code);
-                            found_match = true;
-                        } 
-                    }
-                    9 => {
-                        // Literal Case
-                        if BitStreamInflator::MATCH_9_LOW <= cur_val && cur_val <= BitStreamInflator::MATCH_9_HIGH {
-                            code = cur_val - BitStreamInflator::MATCH_9_LOW + BitStreamInflator::BASE_9_CODE;
-                            self.write_literal_code(code);
-                            found_match = true;
-                        } 
-                    }
-                    _ => {}
-                }
+    // Decodes until the output reaches limit bytes or the block ends, returning true at the end of block
+    fn read_huffman_codes(&mut self, lit_table: &DecodeTable, dist_table: &DecodeTable, limit: usize) -> Result<bool> {
+        while self.look_back_buffer.len() < limit {
+            let code = self.read_symbol(lit_table)?;
+            match code {
+                0..=255 => self.write_literal_code(code),
+                Self::EOB_CODE => return Ok(true),
+                257..=285 => self.repeat_buffer(code, dist_table)?,
+                _ => return Err(self.corrupt(format!("Invalid length code {}", code)))
             }
         }
+        Ok(false)
     }
 
     fn write_literal_code(&mut self, code: usize) {
         let c = code as u8;
         self.look_back_buffer.push(c);
     }
-    
-    fn write_literal_char(&mut self, c: u8) {
-        self.look_back_buffer.push(c as u8);
-    }
 
     const LENGTH_BASES: [usize; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+    const LENGTH_EXTRA_BITS: [usize; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
 
-    fn get_length(&mut self, code: usize) -> usize{
-        let len: usize;
-        let amount_to_read: usize;
-        match code {
-            265..=268 => {amount_to_read = 1;}
-            269..=272 => {amount_to_read = 2;}
-            273..=276 => {amount_to_read = 3;}
-            277..=280 => {amount_to_read = 4;}
-            281..=284 => {amount_to_read = 5;}
-            _ => {amount_to_read = 0;}
-        }
-        let mut next_bits = self.read_ahead(amount_to_read);
-        next_bits.reverse();
-        let add_to_len = binary_to_dec(&next_bits);
-        len = BitStreamInflator::LENGTH_BASES[(code - BitStreamInflator::EOB_CODE - 1)] + add_to_len;
-        return len
+    fn get_length(&mut self, code: usize) -> Result<usize> {
+        let idx = code - Self::EOB_CODE - 1;
+        let add_to_len = self.reader.read_bits(Self::LENGTH_EXTRA_BITS[idx])?;
+        Ok(Self::LENGTH_BASES[idx] + add_to_len)
     }
 
-    fn repeat_buffer(&mut self, code: usize) {
-        let len = self.get_length(code);
-        let distance = self.get_distance();
-        for _ in 0..len {
-            self.write_literal_char(self.look_back_buffer[self.look_back_buffer.len() - distance])
+    fn repeat_buffer(&mut self, code: usize, dist_table: &DecodeTable) -> Result<()> {
+        let len = self.get_length(code)?;
+        let distance = self.get_distance(dist_table)?;
+        if distance > self.look_back_buffer.len() {
+            return Err(Error::InvalidDistance { bit_offset: self.reader.position(), distance, available: self.look_back_buffer.len() });
         }
+        let start = self.look_back_buffer.len() - distance;
+        for i in start..start + len {
+            self.look_back_buffer.push(self.look_back_buffer[i]);
+        }
+        Ok(())
     }
 
     const REPEAT_BASES: [usize; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+    const REPEAT_EXTRA_BITS: [usize; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
 
-    fn get_distance(&mut self) -> usize{
-        let distance: usize;
-        let amount_to_read: usize;
-        let distance_bits = self.read_ahead(5);
-        let code = binary_to_dec(&distance_bits);
-        match code{
-            4..=5 => {amount_to_read = 1}
-            6..=7 => {amount_to_read = 2}
-            8..=9 => {amount_to_read = 3}
-            10..=11 => {amount_to_read = 4}
-            12..=13 => {amount_to_read = 5}
-            14..=15 => {amount_to_read = 6}
-            16..=17 => {amount_to_read = 7}
-            18..=19 => {amount_to_read = 8}
-            20..=21 => {amount_to_read = 9}
-            22..=23 => {amount_to_read = 10}
-            24..=25 => {amount_to_read = 11}
-            26..=27 => {amount_to_read = 12}
-            28..=29 => {amount_to_read = 13}
-            _ => {amount_to_read = 0}
+    fn get_distance(&mut self, dist_table: &DecodeTable) -> Result<usize> {
+        let code = self.read_symbol(dist_table)?;
+        if code >= 30 {
+            return Err(self.corrupt(format!("Invalid distance code {}", code)));
         }
-        let mut next_bits = self.read_ahead(amount_to_read);
-        next_bits.reverse();
-        let add_to_distance = binary_to_dec(&next_bits);
-        distance = BitStreamInflator::REPEAT_BASES[code] + add_to_distance;
-        return distance;
+        let add_to_distance = self.reader.read_bits(Self::REPEAT_EXTRA_BITS[code])?;
+        Ok(Self::REPEAT_BASES[code] + add_to_distance)
     }
 
     const CODE_LEN_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
 
-    fn read_dynamic_huffman(&mut self) {
-        let mut bl_count_temp: HashMap<usize, usize> = HashMap::new();
-
-        let mut hlitvec = self.read_ahead(5);
-        hlitvec.reverse();
-        let hlit = binary_to_dec(&hlitvec);
-        let mut hdistvec = self.read_ahead(5);
-        hdistvec.reverse();
-        let hdist = binary_to_dec(&hdistvec);
-        let mut hclenvec = self.read_ahead(4);
-        hclenvec.reverse();
-        let hclen = binary_to_dec(&hclenvec);
-
-        let mut cl_bet: HashMap<usize, usize> = HashMap::new();
-        for (i, num) in BitStreamInflator::CODE_LEN_ORDER.iter().enumerate(){
-            if i < hclen + 4{
-                let mut bits = self.read_ahead(3);
-                bits.reverse();
-                let code_len = binary_to_dec(&bits);
-                cl_bet.insert(*num, code_len);
-            }
-        }
+    fn read_dynamic_huffman(&mut self) -> Result<BlockState> {
+        let hlit = self.reader.read_bits(5)? + 257;
+        let hdist = self.reader.read_bits(5)? + 1;
+        let hclen = self.reader.read_bits(4)? + 4;
 
-        let mut maxlen = 0;
-        for (code, length) in cl_bet.iter(){
-            bl_count_temp.entry(*length).and_modify(|count| *count += 1).or_insert(1);
-            if length > &maxlen {
-                maxlen = *length;
-            } 
+        let mut cl_lens: Vec<usize> = vec![0; 19];
+        for symbol in Self::CODE_LEN_ORDER.iter().take(hclen) {
+            cl_lens[*symbol] = self.reader.read_bits(3)?;
         }
-
-        let mut bl_count: Vec<usize> = Vec::new();
-        bl_count.push(0);
-        for i in 1..=maxlen{
-            match bl_count_temp.get_mut(&i){
-                Some(v) => bl_count.push(*v),
-                None => bl_count.push(0)
-            }
+        let cl_table = DecodeTable::new(&cl_lens, 7).ok_or_else(|| self.corrupt("Invalid code length code".to_string()))?;
+
+        // Literal/length and distance code lengths form one sequence, and runs may cross between them
+        let mut lens: Vec<usize> = Vec::new();
+        while lens.len() < hlit + hdist {
+            let symbol = self.read_symbol(&cl_table)?;
+            let (value, repeat) = match symbol {
+                0..=15 => (symbol, 1),
+                16 => {
+                    let prev = *lens.last().ok_or_else(|| self.corrupt("Repeat with no previous code length".to_string()))?;
+                    (prev, self.reader.read_bits(2)? + 3)
+                }
+                17 => (0, self.reader.read_bits(3)? + 3),
+                _ => (0, self.reader.read_bits(7)? + 11)
+            };
+            lens.extend(std::iter::repeat_n(value, repeat));
         }
-
-        let mut code: usize = 0;
-        let mut next_code: Vec<usize> = Vec::new();
-        let mut next_code_seen: Vec<usize> = Vec::new();
-        next_code.push(0);
-        for i in 1..=maxlen{
-            code = (code + bl_count[i as usize - 1] as usize) << 1;
-            next_code.push(code);
-            next_code_seen.push(0);
+        if lens.len() > hlit + hdist {
+            return Err(self.corrupt("Code lengths run past HLIT + HDIST".to_string()));
         }
 
-        let mut cl_map: HashMap<usize, HashMap<usize, usize>> = HashMap::new();
+        let lit_table = DecodeTable::new(&lens[..hlit], Self::LIT_PRIMARY_BITS).ok_or_else(|| self.corrupt("Invalid literal/length code".to_string()))?;
+        let dist_table = DecodeTable::new(&lens[hlit..], Self::DIST_PRIMARY_BITS).ok_or_else(|| self.corrupt("Invalid distance code".to_string()))?;
+        Ok(BlockState::Huffman(lit_table, dist_table))
+    }
 
-        for i in 0..=18 {
-            if cl_bet.contains_key(&i) {
-                let len = cl_bet.get(&i).unwrap();
-                let new_code = next_code[*len as usize];
-                if *len != 0{
-                    next_code[*len as usize] += 1;
-                }
-                if !cl_map.contains_key(len) {
-                    cl_map.insert(*len, HashMap::new());
+    // Decodes until look_back_buffer holds at least limit bytes or the stream ends
+    fn read_until(&mut self, limit: usize) -> Result<()> {
+        while self.look_back_buffer.len() < limit {
+            match std::mem::replace(&mut self.state, BlockState::Header) {
+                BlockState::Header if self.finished => {
+                    self.state = BlockState::Done;
                 }
-                cl_map.get_mut(len).unwrap().insert(new_code, i);
-            }
-        }
-
-        //hlit decode
-        let mut hlit_bet: HashMap<usize, usize> = HashMap::new();
-        let mut max_read = hlit + 257;
-        let mut count = 0;
-        let mut prev: (usize, usize) = (0, 0);
-        while count < max_read{
-            let mut cur_chunk: Vec<bool> = Vec::new();
-            let mut found_match = false;
-            while !found_match {
-                cur_chunk.push(self.get_next_bit());
-                let mut cur_val = binary_to_dec(&cur_chunk);
-                if cl_map.contains_key(&cur_chunk.len()) {
-                    if cl_map.get(&cur_chunk.len()).unwrap().contains_key(&cur_val) {
-                        let num = cl_map.get(&cur_chunk.len()).unwrap().get(&cur_val).unwrap();
-                        let amount_to_read: usize;
-                        match num{
-                            16 => {
-                                amount_to_read = 2;
-                                let mut next_bits = self.read_ahead(amount_to_read);
-                                next_bits.reverse();
-                                let ahead = binary_to_dec(&next_bits) + 3;
-                                for i in 0..ahead{
-                                    if prev.0 <= 15 {
-                                        hlit_bet.insert(count, prev.0);
-                                        count += 1
-                                    }
-                                    if prev.0 == 17 || prev.0 == 18 {
-                                        count += prev.1
-                                    }
-                                }
-                            }
-                            17 => {
-                                amount_to_read = 3;
-                                let mut next_bits = self.read_ahead(amount_to_read);
-                                next_bits.reverse();
-                                let ahead = binary_to_dec(&next_bits) + 3;
-                                count += ahead;
-                                prev = (*num as usize, ahead);
-                            }
-                            18 => {
-                                amount_to_read = 7;
-                                let mut next_bits = self.read_ahead(amount_to_read);
-                                next_bits.reverse();
-                                let ahead = binary_to_dec(&next_bits) + 11;
-                                count += ahead;
-                                prev = (*num as usize, ahead);
-                            }
-                            _ => {
-                                hlit_bet.insert(count, *num as usize);
-                                prev = (*num as usize, 0);
-                                count += 1;
-                            }
-                        }
-                        found_match = true;
+                BlockState::Header => self.block_read()?,
+                BlockState::Stored(remaining) => {
+                    let remaining = self.copy_stored(remaining, limit)?;
+                    if remaining > 0 {
+                        self.state = BlockState::Stored(remaining);
                     }
                 }
-            }
-        }
-
-        let mut bl_count_temp: HashMap<usize, usize> = HashMap::new();
-        let mut maxlen = 0;
-        for (code, length) in hlit_bet.iter(){
-            bl_count_temp.entry(*length).and_modify(|count| *count += 1).or_insert(1);
-            if length > &maxlen {
-                maxlen = *length;
-            } 
-        }
-
-        let mut bl_count: Vec<usize> = Vec::new();
-        bl_count.push(0);
-        for i in 1..=maxlen{
-            match bl_count_temp.get_mut(&i){
-                Some(v) => bl_count.push(*v),
-                None => bl_count.push(0)
-            }
-        }
-
-        let mut code: usize = 0;
-        let mut next_code: Vec<usize> = Vec::new();
-        let mut next_code_seen: Vec<usize> = Vec::new();
-        next_code.push(0);
-        for i in 1..=maxlen{
-            code = (code + bl_count[i as usize - 1] as usize) << 1;
-            next_code.push(code);
-            next_code_seen.push(0);
-        }
-        
-        let mut hlit_map: HashMap<usize, HashMap<usize, usize>> = HashMap::new();
-
-        for i in 0..=285{
-            if i < hlit + 257{
-                if hlit_bet.contains_key(&i) {
-                    let len = hlit_bet.get(&i).unwrap();
-                    let new_code = next_code[*len as usize];
-                    if *len != 0{
-                        next_code[*len as usize] += 1;
+                BlockState::Huffman(lit_table, dist_table) => {
+                    if !self.read_huffman_codes(&lit_table, &dist_table, limit)? {
+                        self.state = BlockState::Huffman(lit_table, dist_table);
                     }
-                    if !hlit_map.contains_key(len) {
-                        hlit_map.insert(*len, HashMap::new());
-                    }
-                    hlit_map.get_mut(len).unwrap().insert(new_code, i);
                 }
-            }
-        }
-
-        let mut hdist_bet: HashMap<usize, usize> = HashMap::new();
-        count = 0;
-        max_read = hdist + 1;
-        while count < max_read{
-            let mut cur_chunk: Vec<bool> = Vec::new();
-            let mut found_match = false;
-            while !found_match {
-                cur_chunk.push(self.get_next_bit());
-                let mut cur_val = binary_to_dec(&cur_chunk);
-                if cl_map.contains_key(&cur_chunk.len()) {
-                    if cl_map.get(&cur_chunk.len()).unwrap().contains_key(&cur_val) {
-                        let num = cl_map.get(&cur_chunk.len()).unwrap().get(&cur_val).unwrap();
-                        let amount_to_read: usize;
-                        match num{
-                            16 => {
-                                amount_to_read = 2;
-                                let mut next_bits = self.read_ahead(amount_to_read);
-                                next_bits.reverse();
-                                let ahead = binary_to_dec(&next_bits) + 3;
-                                for i in 0..ahead{
-                                    if prev.0 <= 15 {
-                                        hdist_bet.insert(count, prev.0);
-                                        count += 1
-                                    }
-                                    if prev.0 == 17 || prev.0 == 18 {
-                                        count += prev.1
-                                    }
-                                }
-                            }
-                            17 => {
-                                amount_to_read = 3;
-                                let mut next_bits = self.read_ahead(amount_to_read);
-                                next_bits.reverse();
-                                let ahead = binary_to_dec(&next_bits) + 3;
-                                count += ahead;
-                                prev = (*num as usize, ahead);
-                            }
-                            18 => {
-                                amount_to_read = 7;
-                                let mut next_bits = self.read_ahead(amount_to_read);
-                                next_bits.reverse();
-                                let ahead = binary_to_dec(&next_bits) + 11;
-                                count += ahead;
-                                prev = (*num as usize, ahead);
-                            }
-                            _ => {
-                                hdist_bet.insert(count, *num as usize);
-                                prev = (*num as usize, 0);
-                                count += 1;
-                            }
-                        }
-                        found_match = true;
-                    }
+                BlockState::Done => {
+                    self.state = BlockState::Done;
+                    return Ok(());
                 }
             }
         }
-        
-        let mut bl_count_temp: HashMap<usize, usize> = HashMap::new();
-        let mut maxlen = 0;
-        for (code, length) in hdist_bet.iter(){
-            bl_count_temp.entry(*length).and_modify(|count| *count += 1).or_insert(1);
-            if length > &maxlen {
-                maxlen = *length;
-            } 
-        }
+        Ok(())
+    }
 
-        let mut bl_count: Vec<usize> = Vec::new();
-        bl_count.push(0);
-        for i in 1..=maxlen{
-            match bl_count_temp.get_mut(&i){
-                Some(v) => bl_count.push(*v),
-                None => bl_count.push(0)
-            }
-        }
+    pub fn read(&mut self) -> Result<()> {
+        self.read_until(usize::MAX)
+    }
+}
 
-        let mut code: usize = 0;
-        let mut next_code: Vec<usize> = Vec::new();
-        let mut next_code_seen: Vec<usize> = Vec::new();
-        next_code.push(0);
-        for i in 1..=maxlen{
-            code = (code + bl_count[i as usize - 1] as usize) << 1;
-            next_code.push(code);
-            next_code_seen.push(0);
-        }
-        
-        let mut hdist_map: HashMap<usize, HashMap<usize, usize>> = HashMap::new();
-
-        for i in 0..=29{
-            if i < hdist + 1{
-                if hdist_bet.contains_key(&i) {
-                    let len = hdist_bet.get(&i).unwrap();
-                    let new_code = next_code[*len as usize];
-                    if *len != 0{
-                        next_code[*len as usize] += 1;
-                    }
-                    if !hdist_map.contains_key(len) {
-                        hdist_map.insert(*len, HashMap::new());
-                    }
-                    hdist_map.get_mut(len).unwrap().insert(new_code, i);
-                }
-            }
-        }
+const WINDOW_SIZE: usize = 32768;
 
-        let mut code = 0;
-        
+// Decompresses a raw deflate stream from any reader as it is read, keeping only the last 32K of output
+// for back-references
+pub struct DeflateDecoder<R: Read> {
+    inflator: BitStreamInflator<R>,
+    // Bytes at the front of the inflator's buffer already handed out
+    read_pos: usize,
+}
 
-        while code != BitStreamInflator::EOB_CODE {
-            let mut cur_chunk = Vec::new();
-            let mut found_match: bool = false;
-            while !found_match {
-                cur_chunk.push(self.get_next_bit());
-                let cur_val = binary_to_dec(&cur_chunk);
-                if hlit_map.contains_key(&cur_chunk.len()) {
-                    if hlit_map.get(&cur_chunk.len()).unwrap().contains_key(&cur_val) {
-                        found_match = true;
-                        code = *hlit_map.get(&cur_chunk.len()).unwrap().get(&cur_val).unwrap();
-                        match code {
-                            0..=255 => {
-                                self.write_literal_code(code);
-                            }
-                            257..=285 => {
-                                self.repeat_buffer_dynamic(code, &hdist_map);
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-            }
-        }
+impl<R: Read> DeflateDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        DeflateDecoder { inflator: BitStreamInflator::new(reader), read_pos: 0 }
     }
 
-
-    fn repeat_buffer_dynamic(&mut self, code: usize, hdist_map: &HashMap<usize, HashMap<usize, usize>>) {
-        let len = self.get_length(code);
-        let distance = self.get_distance_dynamic(hdist_map);
-        for _ in 0..len {
-            self.write_literal_char(self.look_back_buffer[self.look_back_buffer.len() - distance])
+    fn trim(&mut self) {
+        let buffer = &mut self.inflator.look_back_buffer;
+        let drop = self.read_pos.min(buffer.len().saturating_sub(WINDOW_SIZE));
+        if drop >= WINDOW_SIZE {
+            buffer.drain(..drop);
+            self.read_pos -= drop;
         }
     }
+}
 
-    fn get_distance_dynamic(&mut self, hdist_map: &HashMap<usize, HashMap<usize, usize>>) -> usize{
-        let distance: usize;
-        let amount_to_read: usize;
-        let mut code: usize = 0;
-        let mut found_match: bool = false;
-        let mut cur_chunk = Vec::new();
-        while !found_match {
-            cur_chunk.push(self.get_next_bit());
-            let cur_val = binary_to_dec(&cur_chunk);
-            if hdist_map.contains_key(&cur_chunk.len()) {
-                if hdist_map[&cur_chunk.len()].contains_key(&cur_val) {
-                    found_match = true;
-                    code = hdist_map[&cur_chunk.len()][&cur_val];
-                }
-            }
-        }
-
-        match code{
-            4..=5 => {amount_to_read = 1}
-            6..=7 => {amount_to_read = 2}
-            8..=9 => {amount_to_read = 3}
-            10..=11 => {amount_to_read = 4}
-            12..=13 => {amount_to_read = 5}
-            14..=15 => {amount_to_read = 6}
-            16..=17 => {amount_to_read = 7}
-            18..=19 => {amount_to_read = 8}
-            20..=21 => {amount_to_read = 9}
-            22..=23 => {amount_to_read = 10}
-            24..=25 => {amount_to_read = 11}
-            26..=27 => {amount_to_read = 12}
-            28..=29 => {amount_to_read = 13}
-            _ => {amount_to_read = 0}
-        }
-        let mut next_bits = self.read_ahead(amount_to_read);
-        next_bits.reverse();
-        let add_to_distance = binary_to_dec(&next_bits);
-        distance = BitStreamInflator::REPEAT_BASES[code] + add_to_distance;
-        return distance;
+impl<R: Read> Read for DeflateDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inflator.read_until(self.read_pos + buf.len())?;
+        let available = &self.inflator.look_back_buffer[self.read_pos..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.read_pos += len;
+        self.trim();
+        Ok(len)
     }
+}
 
-    pub fn read(&mut self) {
-        while !self.finished{
-            self.block_read();
-        }
-    }
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DecompressOptions {
+    // Expected output size, used to preallocate the output buffer
+    pub size_hint: usize,
 }
 
-pub fn inflate(args: Vec<String>) {
-    let file_name: &str = &args[1];
-    let mut file = File::open(file_name).expect("No File Found");
-    let mut fdata: Vec<u8> = Vec::new();
-    file.read_to_end(&mut fdata).expect("Unable to read data");
-    let inflated_data: Vec<u8> = inflate_data(fdata);
+pub fn decompress(data: &[u8], options: &DecompressOptions) -> Result<Vec<u8>> {
+    let mut bs = BitStreamInflator::new(data);
+    bs.look_back_buffer.reserve(options.size_hint);
+    bs.read()?;
+    Ok(bs.look_back_buffer)
+}
 
-    let new_name = file_name.split(".deflate").into_iter().collect::<Vec<&str>>().join("");
-    let mut new_file = File::create(&new_name).expect("Unable to create file");
-    new_file.write_all(&inflated_data);
+// Decompresses name.deflate into name
+pub fn inflate_file(file_name: &str) -> Result<()> {
+    let mut decoder = DeflateDecoder::new(BufReader::new(File::open(file_name)?));
+    let new_name = file_name.split(".deflate").collect::<Vec<&str>>().join("");
+    let mut new_file = BufWriter::new(File::create(&new_name)?);
+    io::copy(&mut decoder, &mut new_file)?;
+    new_file.flush()?;
+    Ok(())
 }
 
-pub fn inflate_data(data: Vec<u8>) -> Vec<u8>{
-    let mut bs: BitStreamInflator = BitStreamInflator::new(data);
-    bs.read();
-    return bs.look_back_buffer;
+pub fn inflate_data(data: Vec<u8>) -> Result<Vec<u8>>{
+    decompress(&data, &DecompressOptions::default())
 }
 
-pub fn print_bitstream(args: Vec<String>) {
-    let file_name: &str = &args[1];
-    let mut file = File::open(file_name).expect("No File Found");
-    let mut fdata: Vec<u8> = Vec::new();
-    file.read_to_end(&mut fdata).expect("Unable to read data");
-    let mut bs: BitStreamInflator = BitStreamInflator::new(fdata);
-    let mut bits: Vec<usize> = Vec::new();
-    while bs.byte_pos < bs.data.len() {
-        bits.push(bs.get_next_bit() as usize);
+// Prints the file's bits in the order the inflater reads them, least significant bit of each byte
+// first, eight bytes to a line
+pub fn print_bitstream(file_name: &str) -> Result<()> {
+    let file = File::open(file_name)?;
+    let mut bs = BitStreamInflator::new(file);
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut count: usize = 0;
+    while let Ok(bit) = bs.reader.read_bits(1) {
+        if count > 0 && count.is_multiple_of(64) {
+            writeln!(out)?;
+        }
+        else if count > 0 && count.is_multiple_of(8) {
+            write!(out, " ")?;
+        }
+        write!(out, "{}", bit)?;
+        count += 1;
+    }
+    if count > 0 {
+        writeln!(out)?;
     }
+    out.flush()?;
+    Ok(())
 }
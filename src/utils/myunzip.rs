@@ -1,46 +1,314 @@
-use super::zipfile::*;
 use std::fs;
-use std::env;
 use std::fs::File;
-use std::io::Write;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
 use super::records::*;
-use super::inflate::*;
+use super::zipfile::*;
+use super::crc32::*;
+use super::error::*;
+use super::dostime::*;
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
 use std::str;
+use std::time::SystemTime;
+
+// Longer than any path the OS would accept, so a bigger entry can't be a real link
+const MAX_LINK_TARGET: u64 = 4096;
+// The same limit Linux puts on links followed while resolving one path
+const MAX_LINKS_FOLLOWED: usize = 40;
+
+fn entry_path(fname: &[u8]) -> Result<&str> {
+    str::from_utf8(fname).map_err(|_| Error::InvalidName(fname.to_vec()))
+}
+
+// Turns an entry name into a path relative to the destination. Backslashes count as separators and
+// drive letters and leading slashes are dropped, while a ".." anywhere rejects the entry outright
+fn relative_path(name: &str) -> Option<String> {
+    let name = name.replace('\\', "/");
+    let mut rest = name.as_str();
+    if rest.len() >= 2 && rest.as_bytes()[0].is_ascii_alphabetic() && rest.as_bytes()[1] == b':' {
+        rest = &rest[2..];
+    }
+    let mut parts = Vec::new();
+    for part in rest.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => return None,
+            part => parts.push(part),
+        }
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    let mut path = parts.join("/");
+    if name.ends_with('/') {
+        path.push('/');
+    }
+    Some(path)
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    // Write deflated entries out as name.deflate instead of inflating them
+    pub raw: bool,
+    // Permission bits to clear from the modes stored in the archive
+    pub umask: u32,
+    // Directory everything is extracted below
+    pub dest: PathBuf,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions { raw: false, umask: process_umask(), dest: PathBuf::from(".") }
+    }
+}
+
+// The umask the process was started with. umask() can only be read by setting it, so it is put straight back
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn process_umask() -> u32 {
+    #[cfg(target_os = "linux")]
+    type ModeT = u32;
+    #[cfg(target_os = "macos")]
+    type ModeT = u16;
+    extern "C" {
+        fn umask(mask: ModeT) -> ModeT;
+    }
+    // SAFETY: umask only swaps the process file mode creation mask and can't fail
+    unsafe {
+        let mask = umask(0o022);
+        umask(mask);
+        mask as u32 & 0o777
+    }
+}
+
+// Elsewhere there is no umask to read, so the usual 022 stands in for it
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn process_umask() -> u32 {
+    0o022
+}
+
+// Extracts every entry of the archive at file_name below options.dest. Entries that would land
+// outside it or have names that aren't UTF-8 are skipped and handed back for the caller to report
+pub fn unzip_archive(file_name: &str, options: &ExtractOptions) -> Result<Vec<Error>> {
+    let file = File::open(file_name)?;
+    let mut archive = zip_archive_from_file(BufReader::new(file))?;
+    fs::create_dir_all(&options.dest)?;
+    let mut rejected = Vec::new();
+    // A directory that loses write permission has to wait until everything inside it is written
+    let mut dir_modes: Vec<(PathBuf, u32)> = Vec::new();
+    // Links are only made once every file and directory is written, as Info-ZIP does, so nothing
+    // from the archive is ever written through a link the archive itself created
+    let mut links: Vec<(usize, String)> = Vec::new();
+    for index in 0..archive.len() {
+        let mut name = archive.entries[index].fname.clone();
+        if options.raw && archive.entries[index].comp_method == DEFLATED {
+            name.extend(".deflate".as_bytes());
+        }
+        let temp = match entry_path(&name) {
+            Ok(temp) => temp,
+            Err(err) => {
+                rejected.push(err);
+                continue;
+            }
+        };
+        let Some(relative) = relative_path(temp) else {
+            rejected.push(Error::UnsafePath(temp.to_string()));
+            continue;
+        };
+        if is_symlink(&archive.entries[index]) {
+            links.push((index, relative));
+            continue;
+        }
+        let path = options.dest.join(&relative);
+        let is_dir = relative.ends_with('/');
+        if through_symlink(&options.dest, if is_dir { Path::new(&relative) } else { parent_of(&relative) }) {
+            rejected.push(Error::UnsafePath(temp.to_string()));
+            continue;
+        }
+        if is_dir {
+            fs::create_dir_all(&path)?;
+            if let Some(mode) = unix_mode(&archive.entries[index]) {
+                dir_modes.push((path, mode));
+            }
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // A link left at the entry's own path is replaced, not written through
+        if fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+            fs::remove_file(&path)?;
+        }
+        let mut new_file = BufWriter::new(File::create(&path)?);
+        // Entries are streamed to disk, so a bad one only shows up at the end; don't leave it behind
+        if let Err(err) = extract_entry(&mut archive, index, &mut new_file, options) {
+            fs::remove_file(&path)?;
+            return Err(err);
+        }
+        // Writing the data bumps the modification time, so the stored one can only go on afterwards
+        if let Some(time) = entry_modified(&archive.entries[index]) {
+            new_file.get_ref().set_modified(time)?;
+        }
+        if let Some(mode) = unix_mode(&archive.entries[index]) {
+            set_permissions(&path, mode, options.umask)?;
+        }
+    }
+    // Links made earlier in this loop are on disk by the time later ones are checked
+    for (index, name) in links {
+        if through_symlink(&options.dest, parent_of(&name)) {
+            rejected.push(Error::UnsafePath(name));
+            continue;
+        }
+        if let Some(parent) = options.dest.join(&name).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match extract_symlink(&mut archive, index, &options.dest, &name) {
+            Err(err @ Error::UnsafeSymlink { .. }) => rejected.push(err),
+            result => result?,
+        }
+    }
+    for (dir, mode) in dir_modes.iter().rev() {
+        set_permissions(dir, *mode, options.umask)?;
+    }
+    Ok(rejected)
+}
+
+fn parent_of(relative: &str) -> &Path {
+    Path::new(relative).parent().unwrap_or(Path::new(""))
+}
 
-pub fn gen_unzip_0(args: Vec<String>){
-    let file_name: &str = &args[1];
-    let mut file = File::open(file_name).expect("No File Found");
-    let mut record = lfrecord_from_file(&mut file);
-    if record.comp_method == 8{
-        record.fname.extend(".deflate".as_bytes());
-    }
-    // println!("{:?}", &str::from_utf8(&record.fname.clone()).unwrap());
-    let temp = &record.fname.clone();
-    let split = &str::from_utf8(temp).unwrap().rsplit_once('/');
-    if split.is_some(){
-        fs::create_dir_all(split.unwrap().0);
-    }
-    println!("{:?}", &temp);
-    let mut new_file = File::create(&str::from_utf8(temp).unwrap()).expect("Unable to create file");
-    new_file.write_all(&record.fdata);
-}
-
-pub fn gen_unzip(args: Vec<String>) {
-    let file_name: &str = &args[1];
-    let mut file = File::open(file_name).expect("No File Found");
-    let mut record = lfrecord_from_file(&mut file);
-    let temp = &record.fname.clone();
-    let split = &str::from_utf8(temp).unwrap().rsplit_once('/');
-    if split.is_some(){
-        fs::create_dir_all(split.unwrap().0);
-    }
-    let mut new_file = File::create(&str::from_utf8(temp).unwrap()).expect("Unable to create file");
-    if record.comp_method == 8{
-        record.fname.extend(".deflate".as_bytes());
-        let inflated_data: Vec<u8> = inflate_data(record.fdata);
-        new_file.write_all(&inflated_data);
+// Whether any component of relative below root is already a link, which could lead anywhere
+fn through_symlink(root: &Path, relative: &Path) -> bool {
+    let mut path = root.to_path_buf();
+    for component in relative.components() {
+        path.push(component);
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => return true,
+            Ok(_) => {}
+            Err(_) => return false,
+        }
+    }
+    false
+}
+
+// The link is only made once its target, resolved against what is already on disk, stays below the
+// destination; the link's own mode and times are left to the OS
+fn extract_symlink<R: Read + Seek>(archive: &mut ZipArchive<R>, index: usize, root: &Path, name: &str) -> Result<()> {
+    let mut target = Vec::new();
+    archive.entry_reader(index)?.take(MAX_LINK_TARGET + 1).read_to_end(&mut target)?;
+    if target.len() as u64 > MAX_LINK_TARGET {
+        return Err(Error::InvalidArchive(format!("symlink {} has a target longer than {} bytes", name, MAX_LINK_TARGET)));
+    }
+    let target = String::from_utf8(target).map_err(|err| Error::InvalidName(err.into_bytes()))?;
+    if !symlink_stays_inside(root, name, &target) {
+        return Err(Error::UnsafeSymlink { name: name.to_string(), target });
+    }
+    let path = root.join(name);
+    // Extracting over an earlier run replaces the old link rather than following it
+    match fs::remove_file(&path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+        _ => {}
+    }
+    create_symlink(&target, &path)
+}
+
+fn symlink_stays_inside(root: &Path, name: &str, target: &str) -> bool {
+    let parent = name.rsplit_once('/').map_or("", |(parent, _)| parent);
+    let mut resolved = Vec::new();
+    let mut links_followed = 0;
+    stays_inside(root, &mut resolved, Path::new(parent), &mut links_followed)
+        && stays_inside(root, &mut resolved, Path::new(target), &mut links_followed)
+}
+
+// Walks path from the components already in resolved, expanding links found on disk in place, and
+// fails as soon as it climbs above root or names an absolute location
+fn stays_inside(root: &Path, resolved: &mut Vec<OsString>, path: &Path, links_followed: &mut usize) -> bool {
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                // Only step back out of a directory that is really there; a name that doesn't exist
+                // yet could still turn into a link and take the ".." somewhere else
+                let current = root.join(resolved.iter().collect::<PathBuf>());
+                if !fs::symlink_metadata(current).is_ok_and(|metadata| metadata.is_dir()) || resolved.pop().is_none() {
+                    return false;
+                }
+            }
+            Component::Normal(part) => {
+                resolved.push(part.to_os_string());
+                let on_disk = root.join(resolved.iter().collect::<PathBuf>());
+                if let Ok(link) = fs::read_link(on_disk) {
+                    *links_followed += 1;
+                    resolved.pop();
+                    if *links_followed > MAX_LINKS_FOLLOWED || !stays_inside(root, resolved, &link, links_followed) {
+                        return false;
+                    }
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &str, path: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(target, path)?;
+    Ok(())
+}
+
+// Without unix symlinks the entry comes out as a small file holding the target, as Info-ZIP does
+#[cfg(not(unix))]
+fn create_symlink(target: &str, path: &Path) -> Result<()> {
+    fs::write(path, target)?;
+    Ok(())
+}
+
+// Only the rwx bits are restored; setuid, setgid and sticky from an archive are never trusted
+#[cfg(unix)]
+fn set_permissions(path: &Path, mode: u32, umask: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777 & !umask))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_permissions(_path: &Path, _mode: u32, _umask: u32) -> Result<()> {
+    Ok(())
+}
+
+fn extract_entry<R: Read + Seek, W: Write>(archive: &mut ZipArchive<R>, index: usize, out: &mut W, options: &ExtractOptions) -> Result<()> {
+    if options.raw {
+        let record = archive.read_raw_entry(index)?;
+        match record.comp_method {
+            STORED => check_crc(&record)?,
+            DEFLATED => {}
+            method => return Err(Error::UnsupportedMethod(method))
+        }
+        out.write_all(&record.fdata)?;
     }
     else {
-        new_file.write( &record.fdata);
+        io::copy(&mut archive.entry_reader(index)?, out)?;
     }
-}
\ No newline at end of file
+    out.flush()?;
+    Ok(())
+}
+
+// The UT extra field is exact to the second and in UTC, so it wins over the DOS stamp when present
+fn entry_modified(entry: &CDRecord) -> Option<SystemTime> {
+    let fields = cdrecord_extra_fields(entry).unwrap_or_default();
+    for field in fields {
+        if let ExtraField::ExtendedTimestamp { modified: Some(secs), .. } = field {
+            return Some(system_time_from_unix(secs));
+        }
+    }
+    system_time_from_dos(entry.last_mod_time, entry.last_mod_date)
+}
+
+fn check_crc(record: &LFRecord) -> Result<()> {
+    let crc = crc32(&record.fdata);
+    if crc != record.crc_32 {
+        return Err(Error::CrcMismatch { name: String::from_utf8_lossy(&record.fname).into_owned(), expected: record.crc_32, found: crc });
+    }
+    Ok(())
+}
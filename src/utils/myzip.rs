@@ -1,29 +1,101 @@
 use super::zipfile::*;
-use super::deflate::*;
+use super::error::*;
 use std::fs;
 use std::fs::File;
-use std::io::Read;
-
-pub fn gen_zip_0(args: Vec<String>) {
-    let out_name = &args[1];
-    let fname = &args[2];
-    let mut cur_file = File::open(fname).expect("File Not Found");
-    let mut fdata: Vec<u8> = Vec::new();
-    cur_file.read_to_end(&mut fdata);
-    let mut zip: ZipFile;
-    zip = zip_file_creator(fname.as_bytes().to_owned(), fdata, 0, None);    
-    zip_writer(zip, &out_name);
-}
-
-pub fn gen_zip(args: Vec<String>) {
-    let out_name = &args[1];
-    let fname = &args[2];
-    let mut cur_file = File::open(fname).expect("File Not Found");
-    let mut fdata: Vec<u8> = Vec::new();
-    cur_file.read_to_end(&mut fdata);
-    let ogdata = Some(fdata.clone());
-    let mut zip: ZipFile;
-    let zdata = deflate_data_with_77(fdata);
-    zip = zip_file_creator(fname.as_bytes().to_owned(), zdata, 8, ogdata);    
-    zip_writer(zip, &out_name);
-}
\ No newline at end of file
+use std::io::BufWriter;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ArchiveOptions {
+    pub file: FileOptions,
+    // Store symlinks as links, like zip -y, instead of archiving whatever they point to
+    pub symlinks: bool,
+}
+
+// Expands each path into the entries to archive, directories first with a trailing '/'.
+// With symlinks set, a link is listed as itself and never descended into.
+pub fn collect_paths(paths: &[String], symlinks: bool) -> Result<Vec<String>> {
+    let mut collected: Vec<String> = Vec::new();
+    for path in paths {
+        collect_path(path.trim_end_matches('/'), symlinks, &mut collected)?;
+    }
+    Ok(collected)
+}
+
+fn path_metadata(path: &str, symlinks: bool) -> Result<fs::Metadata> {
+    Ok(if symlinks { fs::symlink_metadata(path)? } else { fs::metadata(path)? })
+}
+
+fn collect_path(path: &str, symlinks: bool, collected: &mut Vec<String>) -> Result<()> {
+    if path_metadata(path, symlinks)?.is_dir() {
+        collected.push(format!("{}/", path));
+        let mut children: Vec<String> = Vec::new();
+        for entry in fs::read_dir(path)? {
+            children.push(entry?.path().to_string_lossy().into_owned());
+        }
+        children.sort();
+        for child in children {
+            collect_path(&child, symlinks, collected)?;
+        }
+    }
+    else {
+        collected.push(path.to_string());
+    }
+    Ok(())
+}
+
+// Archive names are relative, so drop any leading "./" or "/" from the path on disk
+fn entry_name(path: &str) -> Vec<u8> {
+    let mut name = path;
+    while let Some(stripped) = name.strip_prefix("./").or_else(|| name.strip_prefix('/')) {
+        name = stripped;
+    }
+    name.as_bytes().to_owned()
+}
+
+#[cfg(unix)]
+fn file_owner(metadata: &fs::Metadata) -> Option<(u32, u32)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.uid(), metadata.gid()))
+}
+
+#[cfg(not(unix))]
+fn file_owner(_metadata: &fs::Metadata) -> Option<(u32, u32)> {
+    None
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.mode())
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+// Archives each path, recursing into directories, into a new zip file at out_name
+pub fn zip_paths(out_name: &str, paths: &[String], options: &ArchiveOptions) -> Result<()> {
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(out_name)?));
+    for fname in collect_paths(paths, options.symlinks)? {
+        let metadata = path_metadata(fname.trim_end_matches('/'), options.symlinks)?;
+        let entry_options = FileOptions {
+            last_modified: metadata.modified().ok(),
+            unix_owner: file_owner(&metadata),
+            unix_mode: file_mode(&metadata),
+            ..options.file
+        };
+        if fname.ends_with('/') {
+            zip.add_directory(&entry_name(&fname), &entry_options)?;
+        }
+        else if metadata.file_type().is_symlink() {
+            let target = fs::read_link(&fname)?;
+            zip.add_symlink(&entry_name(&fname), target.as_os_str().as_encoded_bytes(), &entry_options)?;
+        }
+        else {
+            zip.add_file(&entry_name(&fname), File::open(&fname)?, &entry_options)?;
+        }
+    }
+    zip.finish()?;
+    Ok(())
+}
@@ -1,9 +1,15 @@
-use std::fs::File;
-use std::io::Write;
-use std::fs;
+use std::io;
 use std::io::Read;
-use std::io::{self, prelude::*, SeekFrom};
 use std::convert::TryInto;
+use super::error::*;
+
+pub const LF_SIG: u32 = 0x04034b50;
+pub const CD_SIG: u32 = 0x02014b50;
+pub const EOCD_SIG: u32 = 0x06054b50;
+// Host system in the high byte of "version made by"; for Unix the top 16 bits of ext_file_attr hold st_mode
+pub const UNIX_HOST: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct LFRecord {
     pub lf_sig: u32,
     pub e_ver: u16,
@@ -21,51 +27,53 @@ pub struct LFRecord {
     pub fdata: Vec<u8>
 } 
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct CDRecord {
-    cd_sig: u32,
-    spec_ver: u8,
-    made_by: u8,
-    extract_ver: u16,
-    gen_flag: u16,
+    pub cd_sig: u32,
+    pub spec_ver: u8,
+    pub made_by: u8,
+    pub extract_ver: u16,
+    pub gen_flag: u16,
     pub comp_method: u16,
-    last_mod_time: u16,
-    last_mod_date: u16,
-    crc_32: u32,
-    comp_fsize: u32,
-    ncomp_fsize: u32,
-    fname_len: u16,
-    exfield_len: u16,
-    f_comment_len: u16,
-    disk_num_start: u16,
-    int_file_attr: u16,
-    ext_file_attr: u32,
-    offset_local_head: u32,
+    pub last_mod_time: u16,
+    pub last_mod_date: u16,
+    pub crc_32: u32,
+    pub comp_fsize: u32,
+    pub ncomp_fsize: u32,
+    pub fname_len: u16,
+    pub exfield_len: u16,
+    pub f_comment_len: u16,
+    pub disk_num_start: u16,
+    pub int_file_attr: u16,
+    pub ext_file_attr: u32,
+    pub offset_local_head: u32,
     pub fname: Vec<u8>,
-    extra_field: Option<Vec<u8>>,
-    file_comment: Option<Vec<u8>>
+    pub extra_field: Option<Vec<u8>>,
+    pub file_comment: Option<Vec<u8>>
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct EOCDRecord {
-    eocd_signature: u32,
-    disk_num: u16,
-    start_disk_num: u16,
-    tot_entries_on_disk: u16,
-    tot_entries: u16,
-    cdr_size: u32,
-    cdr_offset: u32,
-    file_comment_len: u16,
-    file_comment: Option<Vec<u8>>
-}
-
-pub fn lfrecord_creator(comp_method: u16, comp_fsize: u32, uncomp_fsize:u32, fname: Vec<u8>, fdata: Vec<u8>) -> LFRecord {
+    pub eocd_signature: u32,
+    pub disk_num: u16,
+    pub start_disk_num: u16,
+    pub tot_entries_on_disk: u16,
+    pub tot_entries: u16,
+    pub cdr_size: u32,
+    pub cdr_offset: u32,
+    pub file_comment_len: u16,
+    pub file_comment: Option<Vec<u8>>
+}
+
+pub fn lfrecord_creator(comp_method: u16, crc_32: u32, comp_fsize: u32, uncomp_fsize:u32, fname: Vec<u8>, fdata: Vec<u8>) -> LFRecord {
     LFRecord {
-        lf_sig: 0x04034b50,
+        lf_sig: LF_SIG,
         e_ver: 20,
         gen_flag: 0,
         comp_method: comp_method,
         last_mod_time: 0,
         last_mod_date: 0,
-        crc_32: 0xdeadbeef,
+        crc_32: crc_32,
         comp_fsize: comp_fsize,
         uncomp_fsize: uncomp_fsize,
         fname_len: fname.len() as u16,
@@ -76,39 +84,39 @@ pub fn lfrecord_creator(comp_method: u16, comp_fsize: u32, uncomp_fsize:u32, fna
     }
 }
 
-pub fn cdrecord_creator(comp_method: u16, comp_fsize: u32, ncomp_fsize: u32, fname: Vec<u8>) -> CDRecord {
+pub fn cdrecord_creator(comp_method: u16, crc_32: u32, comp_fsize: u32, ncomp_fsize: u32, fname: Vec<u8>, offset_local_head: u32) -> CDRecord {
     CDRecord{
-        cd_sig: 0x02014b50,
+        cd_sig: CD_SIG,
         spec_ver: 30,
-        made_by: 65,
+        made_by: UNIX_HOST,
         extract_ver: 20,
         gen_flag: 0,
         comp_method: comp_method,
         last_mod_time: 0,
         last_mod_date: 0,
-        crc_32: 0xdeadbeef,
+        crc_32: crc_32,
         comp_fsize: comp_fsize,
         ncomp_fsize: ncomp_fsize,
         fname_len: fname.len() as u16,
         exfield_len: 0,
         f_comment_len: 0,
         disk_num_start: 0,
-        int_file_attr: 1,
-        ext_file_attr: 1,
-        offset_local_head: 0,
+        int_file_attr: 0,
+        ext_file_attr: 0,
+        offset_local_head: offset_local_head,
         fname: fname,
         extra_field: None,
         file_comment: None
     }
 }
 
-pub fn eocdrecord_creator(cdr_size: u32, cdr_offset: u32) -> EOCDRecord{
+pub fn eocdrecord_creator(tot_entries: u16, cdr_size: u32, cdr_offset: u32) -> EOCDRecord{
     EOCDRecord{
-        eocd_signature: 0x06054b50,
+        eocd_signature: EOCD_SIG,
         disk_num: 0,
         start_disk_num: 0,
-        tot_entries_on_disk: 1,
-        tot_entries: 1,
+        tot_entries_on_disk: tot_entries,
+        tot_entries: tot_entries,
         cdr_size: cdr_size,
         cdr_offset: cdr_offset,
         file_comment_len: 0,
@@ -118,13 +126,18 @@ pub fn eocdrecord_creator(cdr_size: u32, cdr_offset: u32) -> EOCDRecord{
 
 const  CDRECORD_BASE_SIZE: u32 = 46;
 const  LFRECORD_BASE_SIZE: u32 = 30;
+const  EOCDRECORD_BASE_SIZE: u32 = 22;
 
 pub fn cdrecord_len(cdrecord: &CDRecord) -> u32 {
-    CDRECORD_BASE_SIZE + (cdrecord.fname.len() as u32)
+    CDRECORD_BASE_SIZE + (cdrecord.fname.len() as u32) + (cdrecord.exfield_len as u32) + (cdrecord.f_comment_len as u32)
 }
 
 pub fn lfrecord_len(lfrecord: &LFRecord) -> u32 {
-    LFRECORD_BASE_SIZE + (lfrecord.fname.len() as u32) + (lfrecord.fdata.len() as u32)
+    LFRECORD_BASE_SIZE + (lfrecord.fname.len() as u32) + (lfrecord.exfield_len as u32) + (lfrecord.fdata.len() as u32)
+}
+
+pub fn eocdrecord_len(eocdrecord: &EOCDRecord) -> u32 {
+    EOCDRECORD_BASE_SIZE + (eocdrecord.file_comment_len as u32)
 }
 
 pub fn lfrecord_to_byte_array(lfrecord: LFRecord) -> Vec<u8> {
@@ -195,51 +208,65 @@ pub fn eocdrecord_to_byte_array(eocdrecord: EOCDRecord) -> Vec<u8> {
     return array;
 }
 
-pub fn read_2bytes(file: &mut File, buffer: &mut [u8]) -> u16{
-    file.read(buffer).expect("Unable to read?");
-    let num = u16::from_le_bytes(buffer.try_into().expect("Wrong slice size"));
-    return num
+pub fn read_byte<R: Read>(file: &mut R) -> Result<u8>{
+    let mut buffer = [0; 1];
+    file.read_exact(&mut buffer)?;
+    Ok(buffer[0])
 }
 
-pub fn read_4bytes(file: &mut File, buffer: &mut [u8]) -> u32{
-    file.read(buffer).expect("Unable to read?");
-    let num = u32::from_le_bytes(buffer.try_into().expect("Wrong slice size"));
-    return num
+pub fn read_2bytes<R: Read>(file: &mut R, buffer: &mut [u8; 2]) -> Result<u16>{
+    file.read_exact(buffer)?;
+    let num = u16::from_le_bytes(*buffer);
+    Ok(num)
 }
 
-pub fn read_string(file: &mut File, buffer: &mut Vec<u8>) -> String{
-    file.read(buffer).expect("Unable to read?");
-    let string = String::from_utf8(buffer.clone()).expect("INVALID READ");
-    return string;
+pub fn read_4bytes<R: Read>(file: &mut R, buffer: &mut [u8; 4]) -> Result<u32>{
+    file.read_exact(buffer)?;
+    let num = u32::from_le_bytes(*buffer);
+    Ok(num)
 }
 
-pub fn lfrecord_from_file(file: &mut File) -> LFRecord{
-    let mut buf4 = [0; 4];
-    let mut buf2 = [0; 2];
-    let lf_sig = read_4bytes(file, &mut buf4);
-    let e_ver = read_2bytes(file, &mut buf2);
-    let gen_flag = read_2bytes(file, &mut buf2);
-    let comp_method = read_2bytes(file, &mut buf2);
-    let last_mod_file_time = read_2bytes(file, &mut buf2);
-    let last_mod_file_date = read_2bytes(file, &mut buf2);
-    let crc32 = read_4bytes(file, &mut buf4);
-    let comp_file_size = read_4bytes(file, &mut buf4);
-    let uncomp_file_size = read_4bytes(file, &mut buf4);
-    let file_name_length = read_2bytes(file, &mut buf2);
-    let extra_field_length = read_2bytes(file, &mut buf2);
-    let mut fname_buff: Vec<u8> = vec![0; file_name_length as usize];
-    file.read(&mut fname_buff).expect("Unable to read?");
-    let mut extra_buff = vec![0; extra_field_length as usize];
-    file.read(&mut extra_buff).expect("Unable to read?");
-    let mut fdata_buff: Vec<u8>;
-    if comp_method == 0{
-        fdata_buff = vec![0; uncomp_file_size as usize];
+pub fn read_string<R: Read>(file: &mut R, buffer: &mut Vec<u8>) -> Result<String>{
+    file.read_exact(buffer)?;
+    String::from_utf8(buffer.clone()).map_err(|err| Error::InvalidName(err.into_bytes()))
+}
+
+// len comes from a header, so the buffer only grows as far as the data really goes
+pub fn read_bytes<R: Read>(file: &mut R, len: usize) -> Result<Vec<u8>>{
+    let mut buffer: Vec<u8> = Vec::new();
+    file.take(len as u64).read_to_end(&mut buffer)?;
+    if buffer.len() != len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
     }
-    else{
-        fdata_buff = vec![0; comp_file_size as usize];
+    Ok(buffer)
+}
+
+fn check_signature(expected: u32, found: u32) -> Result<()> {
+    if found != expected {
+        return Err(Error::BadSignature { expected, found });
     }
-    file.read(&mut fdata_buff).expect("unable to read");
-    let record = LFRecord {
+    Ok(())
+}
+
+// Reads everything up to the file data, leaving fdata empty for the caller to fill
+pub fn lfrecord_header_from_file<R: Read>(file: &mut R) -> Result<LFRecord>{
+    let mut buf4 = [0; 4];
+    let mut buf2 = [0; 2];
+    let lf_sig = read_4bytes(file, &mut buf4)?;
+    check_signature(LF_SIG, lf_sig)?;
+    let e_ver = read_2bytes(file, &mut buf2)?;
+    let gen_flag = read_2bytes(file, &mut buf2)?;
+    let comp_method = read_2bytes(file, &mut buf2)?;
+    let last_mod_file_time = read_2bytes(file, &mut buf2)?;
+    let last_mod_file_date = read_2bytes(file, &mut buf2)?;
+    let crc32 = read_4bytes(file, &mut buf4)?;
+    let comp_file_size = read_4bytes(file, &mut buf4)?;
+    let uncomp_file_size = read_4bytes(file, &mut buf4)?;
+    let file_name_length = read_2bytes(file, &mut buf2)?;
+    let extra_field_length = read_2bytes(file, &mut buf2)?;
+    let fname_buff = read_bytes(file, file_name_length as usize)?;
+    let extra_buff = read_bytes(file, extra_field_length as usize)?;
+    Ok(LFRecord {
         lf_sig: lf_sig,
         e_ver: e_ver,
         gen_flag: gen_flag,
@@ -251,10 +278,235 @@ pub fn lfrecord_from_file(file: &mut File) -> LFRecord{
         uncomp_fsize: uncomp_file_size,
         fname_len: file_name_length,
         exfield_len: extra_field_length,
-        fname: fname_buff.clone(),
-        exfield: Some(extra_buff.clone()),
-        fdata: fdata_buff.clone()
-    };
-    return record;
+        fname: fname_buff,
+        exfield: if extra_field_length != 0 { Some(extra_buff) } else { None },
+        fdata: Vec::new()
+    })
+}
+
+pub fn lfrecord_from_file<R: Read>(file: &mut R) -> Result<LFRecord>{
+    let mut record = lfrecord_header_from_file(file)?;
+    if record.comp_method == 0{
+        record.fdata = read_bytes(file, record.uncomp_fsize as usize)?;
+    }
+    else{
+        record.fdata = read_bytes(file, record.comp_fsize as usize)?;
+    }
+    Ok(record)
+}
+
+pub fn cdrecord_from_file<R: Read>(file: &mut R) -> Result<CDRecord>{
+    let mut buf4 = [0; 4];
+    let mut buf2 = [0; 2];
+    let cd_sig = read_4bytes(file, &mut buf4)?;
+    check_signature(CD_SIG, cd_sig)?;
+    let spec_ver = read_byte(file)?;
+    let made_by = read_byte(file)?;
+    let extract_ver = read_2bytes(file, &mut buf2)?;
+    let gen_flag = read_2bytes(file, &mut buf2)?;
+    let comp_method = read_2bytes(file, &mut buf2)?;
+    let last_mod_time = read_2bytes(file, &mut buf2)?;
+    let last_mod_date = read_2bytes(file, &mut buf2)?;
+    let crc_32 = read_4bytes(file, &mut buf4)?;
+    let comp_fsize = read_4bytes(file, &mut buf4)?;
+    let ncomp_fsize = read_4bytes(file, &mut buf4)?;
+    let fname_len = read_2bytes(file, &mut buf2)?;
+    let exfield_len = read_2bytes(file, &mut buf2)?;
+    let f_comment_len = read_2bytes(file, &mut buf2)?;
+    let disk_num_start = read_2bytes(file, &mut buf2)?;
+    let int_file_attr = read_2bytes(file, &mut buf2)?;
+    let ext_file_attr = read_4bytes(file, &mut buf4)?;
+    let offset_local_head = read_4bytes(file, &mut buf4)?;
+    let fname = read_bytes(file, fname_len as usize)?;
+    let extra_field = read_bytes(file, exfield_len as usize)?;
+    let file_comment = read_bytes(file, f_comment_len as usize)?;
+    Ok(CDRecord {
+        cd_sig,
+        spec_ver,
+        made_by,
+        extract_ver,
+        gen_flag,
+        comp_method,
+        last_mod_time,
+        last_mod_date,
+        crc_32,
+        comp_fsize,
+        ncomp_fsize,
+        fname_len,
+        exfield_len,
+        f_comment_len,
+        disk_num_start,
+        int_file_attr,
+        ext_file_attr,
+        offset_local_head,
+        fname,
+        extra_field: if exfield_len != 0 { Some(extra_field) } else { None },
+        file_comment: if f_comment_len != 0 { Some(file_comment) } else { None }
+    })
+}
+
+pub fn eocdrecord_from_file<R: Read>(file: &mut R) -> Result<EOCDRecord>{
+    let mut buf4 = [0; 4];
+    let mut buf2 = [0; 2];
+    let eocd_signature = read_4bytes(file, &mut buf4)?;
+    check_signature(EOCD_SIG, eocd_signature)?;
+    let disk_num = read_2bytes(file, &mut buf2)?;
+    let start_disk_num = read_2bytes(file, &mut buf2)?;
+    let tot_entries_on_disk = read_2bytes(file, &mut buf2)?;
+    let tot_entries = read_2bytes(file, &mut buf2)?;
+    let cdr_size = read_4bytes(file, &mut buf4)?;
+    let cdr_offset = read_4bytes(file, &mut buf4)?;
+    let file_comment_len = read_2bytes(file, &mut buf2)?;
+    let file_comment = read_bytes(file, file_comment_len as usize)?;
+    Ok(EOCDRecord {
+        eocd_signature,
+        disk_num,
+        start_disk_num,
+        tot_entries_on_disk,
+        tot_entries,
+        cdr_size,
+        cdr_offset,
+        file_comment_len,
+        file_comment: if file_comment_len != 0 { Some(file_comment) } else { None }
+    })
+}
+
+pub fn lfrecord_from_bytes(bytes: &[u8]) -> Result<LFRecord>{
+    lfrecord_from_file(&mut &bytes[..])
+}
+
+pub fn cdrecord_from_bytes(bytes: &[u8]) -> Result<CDRecord>{
+    cdrecord_from_file(&mut &bytes[..])
+}
+
+pub fn eocdrecord_from_bytes(bytes: &[u8]) -> Result<EOCDRecord>{
+    eocdrecord_from_file(&mut &bytes[..])
+}
+
+pub const EXTENDED_TIMESTAMP_TAG: u16 = 0x5455;
+pub const UNIX_OWNER_TAG: u16 = 0x7875;
+
+// One tagged block of a record's extra field
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExtraField {
+    // UT: signed Unix seconds. Central directory copies usually only carry the modification time.
+    ExtendedTimestamp { modified: Option<i32>, accessed: Option<i32>, created: Option<i32> },
+    // ux: owner ids as of version 1 of the field
+    UnixOwner { uid: u32, gid: u32 },
+    // Any tag not understood here, or a known one in a form that isn't, kept exactly as read
+    Unknown { tag: u16, data: Vec<u8> },
 }
 
+pub fn extra_fields_from_bytes(bytes: &[u8]) -> Result<Vec<ExtraField>>{
+    let mut fields: Vec<ExtraField> = Vec::new();
+    let mut rest = bytes;
+    let mut buf2 = [0; 2];
+    while !rest.is_empty() {
+        if rest.len() < 4 {
+            return Err(Error::InvalidArchive("extra field ends partway through a block header".to_string()));
+        }
+        let tag = read_2bytes(&mut rest, &mut buf2)?;
+        let len = read_2bytes(&mut rest, &mut buf2)? as usize;
+        if len > rest.len() {
+            return Err(Error::InvalidArchive(format!("extra field block {:04x} runs past the end of the extra field", tag)));
+        }
+        let (data, remaining) = rest.split_at(len);
+        rest = remaining;
+        let field = match tag {
+            EXTENDED_TIMESTAMP_TAG => extended_timestamp_from_bytes(data),
+            UNIX_OWNER_TAG => unix_owner_from_bytes(data),
+            _ => None
+        };
+        fields.push(field.unwrap_or_else(|| ExtraField::Unknown { tag, data: data.to_vec() }));
+    }
+    Ok(fields)
+}
+
+// The flags byte says which times were recorded, but only as many as fit in the block follow it
+fn extended_timestamp_from_bytes(data: &[u8]) -> Option<ExtraField> {
+    let (flags, mut rest) = data.split_first()?;
+    let mut times: [Option<i32>; 3] = [None; 3];
+    for (bit, time) in times.iter_mut().enumerate() {
+        if flags & (1 << bit) != 0 && rest.len() >= 4 {
+            *time = Some(i32::from_le_bytes(rest[..4].try_into().unwrap()));
+            rest = &rest[4..];
+        }
+    }
+    if !rest.is_empty() {
+        return None;
+    }
+    Some(ExtraField::ExtendedTimestamp { modified: times[0], accessed: times[1], created: times[2] })
+}
+
+// Version 1 is a size byte and that many little-endian bytes for each of the uid and gid
+fn unix_owner_from_bytes(data: &[u8]) -> Option<ExtraField> {
+    let (version, mut rest) = data.split_first()?;
+    if *version != 1 {
+        return None;
+    }
+    let mut ids = [0u32; 2];
+    for id in ids.iter_mut() {
+        let (size, after_size) = rest.split_first()?;
+        let size = *size as usize;
+        if size > after_size.len() || after_size[..size].iter().skip(4).any(|byte| *byte != 0) {
+            return None;
+        }
+        *id = after_size[..size].iter().take(4).rev().fold(0, |acc, byte| acc << 8 | *byte as u32);
+        rest = &after_size[size..];
+    }
+    if !rest.is_empty() {
+        return None;
+    }
+    Some(ExtraField::UnixOwner { uid: ids[0], gid: ids[1] })
+}
+
+pub fn extra_fields_to_byte_array(fields: &[ExtraField]) -> Vec<u8> {
+    let mut array: Vec<u8> = Vec::new();
+    for field in fields {
+        let (tag, data) = match field {
+            ExtraField::ExtendedTimestamp { modified, accessed, created } => {
+                let times = [modified, accessed, created];
+                let mut data: Vec<u8> = vec![0];
+                for (bit, time) in times.iter().enumerate() {
+                    if let Some(time) = time {
+                        data[0] |= 1 << bit;
+                        data.extend_from_slice(&time.to_le_bytes());
+                    }
+                }
+                (EXTENDED_TIMESTAMP_TAG, data)
+            }
+            ExtraField::UnixOwner { uid, gid } => {
+                let mut data: Vec<u8> = vec![1, 4];
+                data.extend_from_slice(&uid.to_le_bytes());
+                data.push(4);
+                data.extend_from_slice(&gid.to_le_bytes());
+                (UNIX_OWNER_TAG, data)
+            }
+            ExtraField::Unknown { tag, data } => (*tag, data.clone())
+        };
+        array.extend_from_slice(&tag.to_le_bytes());
+        array.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        array.extend_from_slice(&data);
+    }
+    array
+}
+
+pub fn lfrecord_extra_fields(lfrecord: &LFRecord) -> Result<Vec<ExtraField>>{
+    extra_fields_from_bytes(lfrecord.exfield.as_deref().unwrap_or_default())
+}
+
+pub fn cdrecord_extra_fields(cdrecord: &CDRecord) -> Result<Vec<ExtraField>>{
+    extra_fields_from_bytes(cdrecord.extra_field.as_deref().unwrap_or_default())
+}
+
+pub fn lfrecord_set_extra_fields(lfrecord: &mut LFRecord, fields: &[ExtraField]) {
+    let bytes = extra_fields_to_byte_array(fields);
+    lfrecord.exfield_len = bytes.len() as u16;
+    lfrecord.exfield = if bytes.is_empty() { None } else { Some(bytes) };
+}
+
+pub fn cdrecord_set_extra_fields(cdrecord: &mut CDRecord, fields: &[ExtraField]) {
+    let bytes = extra_fields_to_byte_array(fields);
+    cdrecord.exfield_len = bytes.len() as u16;
+    cdrecord.extra_field = if bytes.is_empty() { None } else { Some(bytes) };
+}
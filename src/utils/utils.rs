@@ -4,4 +4,9 @@ pub mod records;
 pub mod zipfile;
 pub mod inflate;
 pub mod deflate;
-pub mod helpers;
\ No newline at end of file
+pub mod helpers;
+pub mod huffman;
+pub mod crc32;
+pub mod bitstream;
+pub mod error;
+pub mod dostime;
\ No newline at end of file
@@ -1,33 +1,405 @@
 use super::records::*;
-use std::fs::File;
-use std::io::Write;
+use super::crc32::*;
+use super::error::*;
+use super::deflate::*;
+use super::inflate::*;
+use super::dostime::*;
+use std::io;
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Take, Write};
+use std::time::SystemTime;
 
-pub struct ZipFile {
-    lfrecord: LFRecord,
-    cdrecord: CDRecord,
+pub const STORED: u16 = 0;
+pub const DEFLATED: u16 = 8;
+
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+const DOS_DIRECTORY: u32 = 0x10;
+
+#[derive(Debug, Clone, Copy)]
+pub struct FileOptions {
+    pub method: u16,
+    pub compress: CompressOptions,
+    // Stamped on the entry as an MS-DOS date and time, and to the second in a UT extra field;
+    // without one it reads as 1980-01-01 00:00
+    pub last_modified: Option<SystemTime>,
+    // (uid, gid), recorded in a ux extra field
+    pub unix_owner: Option<(u32, u32)>,
+    // st_mode, file type bits included; without one files get 0644 and directories 0755
+    pub unix_mode: Option<u32>,
+}
+
+impl Default for FileOptions {
+    fn default() -> Self {
+        FileOptions { method: DEFLATED, compress: CompressOptions::default(), last_modified: None, unix_owner: None, unix_mode: None }
+    }
+}
+
+impl FileOptions {
+    // Like zip -0, level 0 stores entries as they are rather than wrapping them in stored deflate blocks
+    pub fn with_level(level: u8) -> Self {
+        if level == 0 {
+            return FileOptions { method: STORED, ..FileOptions::default() };
+        }
+        FileOptions { method: DEFLATED, compress: CompressOptions { level, ..CompressOptions::default() }, last_modified: None, unix_owner: None, unix_mode: None }
+    }
+}
+
+// Counts what passes through, so the compressed size is known without holding on to the data
+struct CountingWriter<'a, W: Write> {
+    inner: &'a mut W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.count += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+enum EntrySink<'a, W: Write> {
+    Stored(CountingWriter<'a, W>),
+    Deflated(Box<DeflateEncoder<CountingWriter<'a, W>>>),
+}
+
+impl<W: Write> EntrySink<'_, W> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match self {
+            EntrySink::Stored(out) => out.write_all(buf),
+            EntrySink::Deflated(encoder) => encoder.write_all(buf),
+        }
+    }
+
+    // Returns how many bytes of entry data reached the archive
+    fn finish(self) -> Result<u64> {
+        match self {
+            EntrySink::Stored(out) => Ok(out.count),
+            EntrySink::Deflated(encoder) => Ok(encoder.finish()?.count),
+        }
+    }
+}
+
+// Offset of the CRC-32 in a local header, followed by the compressed and uncompressed sizes
+const LF_CRC_OFFSET: u64 = 14;
+
+// Writes each entry as it is added, holding back only the central directory for finish()
+pub struct ZipWriter<W: Write + Seek> {
+    writer: W,
+    cdrecords: Vec<CDRecord>,
     eocdrecord: EOCDRecord,
 }
 
-pub fn zip_file_creator(fname:Vec<u8> ,fdata: Vec<u8>, comp_method: u16, ogdata: Option<Vec<u8>>) -> ZipFile {
-    let fsize = fdata.len() as u32;
-    let ogsize: u32;
-    if comp_method == 8 && ogdata.is_some(){
-        ogsize = ogdata.unwrap().len() as u32;
+impl<W: Write + Seek> ZipWriter<W> {
+    pub fn new(writer: W) -> Self {
+        ZipWriter { writer, cdrecords: Vec::new(), eocdrecord: eocdrecord_creator(0, 0, 0) }
+    }
+
+    // The data goes straight to the writer as it is read, in chunks; the local header goes out first
+    // with zeroed CRC and sizes and is patched once they are known. A name or entry count that
+    // doesn't fit is refused before anything is written, but an entry that takes the sizes or
+    // offsets past 4 GiB is only found partway through and leaves the archive unusable.
+    pub fn add_file<R: Read>(&mut self, name: &[u8], mut source: R, options: &FileOptions) -> Result<()> {
+        if options.method != STORED && options.method != DEFLATED {
+            return Err(Error::UnsupportedMethod(options.method));
+        }
+        let display_name = String::from_utf8_lossy(name).into_owned();
+        let (lfrecord, mut cdrecord) = self.entry_records(name, options)?;
+        let tot_entries = self.eocdrecord.tot_entries.checked_add(1).ok_or_else(|| Error::TooLarge("more than 65535 entries".to_string()))?;
+        let cdr_size = self.eocdrecord.cdr_size.checked_add(cdrecord_len(&cdrecord)).ok_or_else(|| Error::TooLarge(format!("the central directory past {}", display_name)))?;
+        let header_start = self.eocdrecord.cdr_offset as u64;
+        let header = lfrecord_to_byte_array(lfrecord);
+        self.writer.write_all(&header)?;
+
+        let out = CountingWriter { inner: &mut self.writer, count: 0 };
+        let mut sink = match options.method {
+            STORED => EntrySink::Stored(out),
+            _ => EntrySink::Deflated(Box::new(DeflateEncoder::new(out, &options.compress))),
+        };
+        let mut crc_32: u32 = 0;
+        let mut uncomp_fsize: u64 = 0;
+        let mut buffer: Vec<u8> = vec![0; 1 << 16];
+        loop {
+            let len = match source.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into())
+            };
+            crc_32 = crc32_update(crc_32, &buffer[..len]);
+            uncomp_fsize += len as u64;
+            if uncomp_fsize > u32::MAX as u64 {
+                return Err(Error::TooLarge(format!("{} is over {} bytes", display_name, u32::MAX)));
+            }
+            sink.write_all(&buffer[..len])?;
+        }
+        let comp_fsize = sink.finish()?;
+        let comp_fsize = u32::try_from(comp_fsize).map_err(|_| Error::TooLarge(format!("{} compresses to {} bytes", display_name, comp_fsize)))?;
+        let end_offset = header_start + header.len() as u64 + comp_fsize as u64;
+        let cdr_offset = u32::try_from(end_offset).map_err(|_| Error::TooLarge(format!("{} would end at offset {}", display_name, end_offset)))?;
+
+        let mut sizes = Vec::with_capacity(12);
+        sizes.extend_from_slice(&crc_32.to_le_bytes());
+        sizes.extend_from_slice(&comp_fsize.to_le_bytes());
+        sizes.extend_from_slice(&(uncomp_fsize as u32).to_le_bytes());
+        self.writer.seek(SeekFrom::Start(header_start + LF_CRC_OFFSET))?;
+        self.writer.write_all(&sizes)?;
+        self.writer.seek(SeekFrom::Start(end_offset))?;
+
+        cdrecord.crc_32 = crc_32;
+        cdrecord.comp_fsize = comp_fsize;
+        cdrecord.ncomp_fsize = uncomp_fsize as u32;
+        self.eocdrecord.tot_entries = tot_entries;
+        self.eocdrecord.tot_entries_on_disk = tot_entries;
+        self.eocdrecord.cdr_size = cdr_size;
+        self.eocdrecord.cdr_offset = cdr_offset;
+        self.cdrecords.push(cdrecord);
+        Ok(())
+    }
+
+    // Stored the way Info-ZIP does it: the link target is the entry's data and S_IFLNK marks it as a link
+    pub fn add_symlink(&mut self, name: &[u8], target: &[u8], options: &FileOptions) -> Result<()> {
+        let unix_mode = S_IFLNK | options.unix_mode.map_or(0o777, |mode| mode & !S_IFMT);
+        self.add_file(name, target, &FileOptions { method: STORED, unix_mode: Some(unix_mode), ..*options })
+    }
+
+    // Only the name and last_modified of options apply to a directory
+    pub fn add_directory(&mut self, name: &[u8], options: &FileOptions) -> Result<()> {
+        let mut name = name.to_vec();
+        if !name.ends_with(b"/") {
+            name.push(b'/');
+        }
+        self.add_file(&name, io::empty(), &FileOptions { method: STORED, ..*options })
     }
-    else{
-        ogsize = fsize.clone();
+
+    // Both headers for an entry, with the CRC and sizes left at zero until the data has been written
+    fn entry_records(&self, name: &[u8], options: &FileOptions) -> Result<(LFRecord, CDRecord)> {
+        if name.len() > u16::MAX as usize {
+            return Err(Error::TooLarge(format!("a {} byte entry name", name.len())));
+        }
+        // Local headers are laid out back to back, so the next one starts where the central directory currently does
+        let offset_local_head = self.eocdrecord.cdr_offset;
+        let (last_mod_time, last_mod_date) = options.last_modified.map_or(DOS_EPOCH, dos_datetime);
+        let mut extra_fields: Vec<ExtraField> = Vec::new();
+        if let Some(time) = options.last_modified {
+            extra_fields.push(ExtraField::ExtendedTimestamp { modified: Some(unix_seconds(time)), accessed: None, created: None });
+        }
+        if let Some((uid, gid)) = options.unix_owner {
+            extra_fields.push(ExtraField::UnixOwner { uid, gid });
+        }
+        let mut lfrecord = lfrecord_creator(options.method, 0, 0, 0, name.to_vec(), Vec::new());
+        lfrecord.last_mod_time = last_mod_time;
+        lfrecord.last_mod_date = last_mod_date;
+        lfrecord_set_extra_fields(&mut lfrecord, &extra_fields);
+        let mut cdrecord = cdrecord_creator(options.method, 0, 0, 0, name.to_vec(), offset_local_head);
+        cdrecord.last_mod_time = last_mod_time;
+        cdrecord.last_mod_date = last_mod_date;
+        cdrecord_set_extra_fields(&mut cdrecord, &extra_fields);
+        let is_dir = name.ends_with(b"/");
+        let unix_mode = options.unix_mode.unwrap_or(if is_dir { S_IFDIR | 0o755 } else { S_IFREG | 0o644 });
+        // The low byte keeps the MS-DOS attributes, of which only the directory bit means anything here
+        cdrecord.ext_file_attr = (unix_mode << 16) | if is_dir { DOS_DIRECTORY } else { 0 };
+        Ok((lfrecord, cdrecord))
+    }
+
+    // Writes the central directory and end record, handing back the underlying writer
+    pub fn finish(mut self) -> Result<W> {
+        for cdrecord in std::mem::take(&mut self.cdrecords) {
+            self.writer.write_all(&cdrecord_to_byte_array(cdrecord))?;
+        }
+        self.writer.write_all(&eocdrecord_to_byte_array(self.eocdrecord))?;
+        self.writer.flush()?;
+        Ok(self.writer)
     }
-    let lfrecord = lfrecord_creator(comp_method, fsize, ogsize, fname.clone(), fdata);
-    let cdrecord = cdrecord_creator(comp_method, fsize, ogsize, fname.clone());
-    let eocdrecord = eocdrecord_creator(cdrecord_len(&cdrecord), lfrecord_len(&lfrecord));
-    ZipFile { lfrecord, cdrecord, eocdrecord}
 }
 
-pub fn zip_writer(zip_file: ZipFile, write_file_name: &str) {
-    let mut file = File::create(write_file_name).expect("Unable to create file");
-    let mut byte_array: Vec<u8> = Vec::new();
-    byte_array.extend(&lfrecord_to_byte_array(zip_file.lfrecord));
-    byte_array.extend(&cdrecord_to_byte_array(zip_file.cdrecord));
-    byte_array.extend(&eocdrecord_to_byte_array(zip_file.eocdrecord));
-    file.write(&byte_array).expect("Unable to write zip");
+const EOCD_BASE_SIZE: u64 = 22;
+const MAX_COMMENT_LEN: u64 = 0xffff;
+
+// st_mode of an entry, if the archive was written on Unix and recorded one
+pub fn unix_mode(record: &CDRecord) -> Option<u32> {
+    let mode = record.ext_file_attr >> 16;
+    if record.made_by != UNIX_HOST || mode == 0 {
+        return None;
+    }
+    Some(mode)
+}
+
+pub fn is_symlink(record: &CDRecord) -> bool {
+    unix_mode(record).is_some_and(|mode| mode & S_IFMT == S_IFLNK)
+}
+
+pub struct ZipArchive<R: Read + Seek> {
+    reader: R,
+    pub entries: Vec<CDRecord>,
+    // Bytes in front of the archive proper (e.g. a self-extractor stub) that the stored offsets don't count
+    base_offset: u64,
+}
+
+pub fn zip_archive_from_file<R: Read + Seek>(mut reader: R) -> Result<ZipArchive<R>> {
+    let eocd_pos = find_eocd(&mut reader)?;
+    reader.seek(SeekFrom::Start(eocd_pos))?;
+    let eocdrecord = eocdrecord_from_file(&mut reader)?;
+    let cd_start = eocd_pos.checked_sub(eocdrecord.cdr_size as u64)
+        .ok_or_else(|| Error::InvalidArchive("central directory size runs past the start of the file".to_string()))?;
+    let base_offset = cd_start.checked_sub(eocdrecord.cdr_offset as u64)
+        .ok_or_else(|| Error::InvalidArchive("central directory offset runs past the start of the file".to_string()))?;
+    reader.seek(SeekFrom::Start(cd_start))?;
+    let mut entries: Vec<CDRecord> = Vec::new();
+    for _ in 0..eocdrecord.tot_entries {
+        entries.push(cdrecord_from_file(&mut reader)?);
+    }
+    Ok(ZipArchive { reader, entries, base_offset })
+}
+
+// Scans backward from the end, since the EOCD record can be followed by an archive comment
+fn find_eocd<R: Read + Seek>(reader: &mut R) -> Result<u64> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let search_len = len.min(EOCD_BASE_SIZE + MAX_COMMENT_LEN);
+    if search_len < EOCD_BASE_SIZE {
+        return Err(Error::InvalidArchive("too short to be a zip file".to_string()));
+    }
+    let search_start = len - search_len;
+    reader.seek(SeekFrom::Start(search_start))?;
+    let mut tail: Vec<u8> = vec![0; search_len as usize];
+    reader.read_exact(&mut tail)?;
+    for i in (0..=(search_len - EOCD_BASE_SIZE) as usize).rev() {
+        if tail[i..i + 4] == EOCD_SIG.to_le_bytes() {
+            let comment_len = u16::from_le_bytes([tail[i + 20], tail[i + 21]]) as usize;
+            if i + EOCD_BASE_SIZE as usize + comment_len == tail.len() {
+                return Ok(search_start + i as u64);
+            }
+        }
+    }
+    Err(Error::InvalidArchive("no end of central directory record found".to_string()))
+}
+
+// A decompressed entry whose CRC has been checked
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    pub name: Vec<u8>,
+    pub data: Vec<u8>,
+    pub record: CDRecord,
+}
+
+impl ZipEntry {
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with(b"/")
+    }
+}
+
+enum EntryData<'a, R: Read> {
+    Stored(Take<&'a mut R>),
+    Deflated(DeflateDecoder<Take<&'a mut R>>),
+}
+
+// Reads one entry's decompressed data straight from the archive, failing with a CRC mismatch at the end
+// if the data doesn't check out
+pub struct ZipEntryReader<'a, R: Read> {
+    data: EntryData<'a, R>,
+    crc_32: u32,
+    pub record: CDRecord,
+}
+
+impl<R: Read> Read for ZipEntryReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = match &mut self.data {
+            EntryData::Stored(reader) => reader.read(buf)?,
+            EntryData::Deflated(decoder) => decoder.read(buf)?
+        };
+        if len == 0 && !buf.is_empty() && self.crc_32 != self.record.crc_32 {
+            let name = String::from_utf8_lossy(&self.record.fname).into_owned();
+            return Err(Error::CrcMismatch { name, expected: self.record.crc_32, found: self.crc_32 }.into());
+        }
+        self.crc_32 = crc32_update(self.crc_32, &buf[..len]);
+        Ok(len)
+    }
+}
+
+pub struct ZipEntries<'a, R: Read + Seek> {
+    archive: &'a mut ZipArchive<R>,
+    index: usize,
+}
+
+impl<R: Read + Seek> Iterator for ZipEntries<'_, R> {
+    type Item = Result<ZipEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.archive.len() {
+            return None;
+        }
+        self.index += 1;
+        Some(self.archive.entry(self.index - 1))
+    }
+}
+
+fn entry_at(entries: &[CDRecord], index: usize) -> Result<&CDRecord> {
+    entries.get(index).ok_or(Error::NoSuchEntry { index, len: entries.len() })
+}
+
+impl<R: Read + Seek> ZipArchive<R> {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&mut self) -> ZipEntries<'_, R> {
+        ZipEntries { archive: self, index: 0 }
+    }
+
+    pub fn entry(&mut self, index: usize) -> Result<ZipEntry> {
+        let mut reader = self.entry_reader(index)?;
+        let mut data: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let record = reader.record;
+        Ok(ZipEntry { name: record.fname.clone(), data, record })
+    }
+
+    pub fn entry_reader(&mut self, index: usize) -> Result<ZipEntryReader<'_, R>> {
+        let record = self.seek_to_data(index)?;
+        let cdrecord = entry_at(&self.entries, index)?.clone();
+        let compressed = (&mut self.reader).take(cdrecord.comp_fsize as u64);
+        let data = match record.comp_method {
+            STORED => EntryData::Stored(compressed),
+            DEFLATED => EntryData::Deflated(DeflateDecoder::new(compressed)),
+            method => return Err(Error::UnsupportedMethod(method))
+        };
+        Ok(ZipEntryReader { data, crc_32: 0, record: cdrecord })
+    }
+
+    pub fn index_by_name(&self, name: &[u8]) -> Option<usize> {
+        self.entries.iter().position(|cdrecord| cdrecord.fname == name)
+    }
+
+    pub fn by_name(&mut self, name: &[u8]) -> Option<Result<ZipEntry>> {
+        self.index_by_name(name).map(|index| self.entry(index))
+    }
+
+    // The entry exactly as stored: fdata is still compressed and nothing checks it against the CRC
+    pub fn read_raw_entry(&mut self, index: usize) -> Result<LFRecord> {
+        let mut record = self.seek_to_data(index)?;
+        record.fdata = read_bytes(&mut self.reader, record.comp_fsize as usize)?;
+        Ok(record)
+    }
+
+    // Reads the local header, leaving the reader at the start of the entry's data
+    fn seek_to_data(&mut self, index: usize) -> Result<LFRecord> {
+        let cdrecord = entry_at(&self.entries, index)?;
+        self.reader.seek(SeekFrom::Start(self.base_offset + cdrecord.offset_local_head as u64))?;
+        let mut record = lfrecord_header_from_file(&mut self.reader)?;
+        // Entries written with a data descriptor leave these zeroed in the local header, so trust the central directory
+        record.crc_32 = cdrecord.crc_32;
+        record.comp_fsize = cdrecord.comp_fsize;
+        record.uncomp_fsize = cdrecord.ncomp_fsize;
+        Ok(record)
+    }
 }